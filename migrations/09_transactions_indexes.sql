@@ -0,0 +1,7 @@
+CREATE INDEX transactions_user_id_transaction_date_idx
+ON transactions(user_id, transaction_date DESC, id DESC);
+
+CREATE INDEX transactions_user_id_amount_idx
+ON transactions(user_id, amount DESC, id DESC);
+
+CREATE INDEX transactions_category_id_idx ON transactions(category_id);
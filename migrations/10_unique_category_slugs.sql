@@ -0,0 +1,20 @@
+-- fold duplicate categories into the oldest one before enforcing uniqueness
+WITH ranked AS (
+    SELECT
+        id,
+        FIRST_VALUE(id) OVER (PARTITION BY user_id, slug ORDER BY created_at, id) AS keep_id
+    FROM categories
+)
+UPDATE transactions t
+SET category_id = ranked.keep_id
+FROM ranked
+WHERE t.category_id = ranked.id AND ranked.id <> ranked.keep_id;
+
+DELETE FROM categories c
+USING categories older
+WHERE c.user_id = older.user_id
+AND c.slug = older.slug
+AND (older.created_at, older.id) < (c.created_at, c.id);
+
+ALTER TABLE categories
+ADD CONSTRAINT categories_user_id_slug_key UNIQUE (user_id, slug);
@@ -0,0 +1,17 @@
+CREATE TYPE account_type AS ENUM ('checking', 'savings', 'credit_card', 'cash', 'investment');
+
+CREATE TABLE accounts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    type account_type NOT NULL DEFAULT 'checking',
+    opening_balance DECIMAL(12, 2) NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, slug)
+);
+
+ALTER TABLE transactions
+ADD COLUMN account_id UUID REFERENCES accounts(id) ON DELETE SET NULL;
+
+CREATE INDEX transactions_account_id_idx ON transactions(account_id);
@@ -0,0 +1,22 @@
+CREATE TYPE transfer_direction AS ENUM ('outgoing', 'incoming');
+
+CREATE TABLE transfers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- a transfer is stored as two linked transactions without a category,
+-- so category based reports and budgets never see them
+ALTER TABLE transactions
+ALTER COLUMN category_id DROP NOT NULL,
+ADD COLUMN transfer_id UUID REFERENCES transfers(id) ON DELETE CASCADE,
+ADD COLUMN transfer_direction transfer_direction,
+ADD CONSTRAINT transactions_category_or_transfer
+    CHECK ((category_id IS NULL) = (transfer_id IS NOT NULL)),
+ADD CONSTRAINT transactions_transfer_direction
+    CHECK ((transfer_id IS NULL) = (transfer_direction IS NULL));
+
+CREATE UNIQUE INDEX transactions_transfer_leg_idx
+ON transactions(transfer_id, transfer_direction)
+WHERE transfer_id IS NOT NULL;
@@ -0,0 +1,57 @@
+ALTER TABLE users
+ADD COLUMN base_currency TEXT NOT NULL DEFAULT 'USD'
+    CHECK (base_currency ~ '^[A-Z]{3}$');
+
+-- everything recorded so far was implicitly in the owner's base currency
+ALTER TABLE transactions
+ADD COLUMN currency TEXT CHECK (currency ~ '^[A-Z]{3}$');
+
+UPDATE transactions t
+SET currency = u.base_currency
+FROM users u
+WHERE u.id = t.user_id;
+
+ALTER TABLE transactions
+ALTER COLUMN currency SET NOT NULL;
+
+-- rates are maintained by every user for themselves, one unit of from_currency
+-- is worth `rate` units of to_currency starting on effective_date
+CREATE TABLE exchange_rates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    from_currency TEXT NOT NULL CHECK (from_currency ~ '^[A-Z]{3}$'),
+    to_currency TEXT NOT NULL CHECK (to_currency ~ '^[A-Z]{3}$'),
+    rate DECIMAL(20, 10) NOT NULL CHECK (rate > 0),
+    effective_date DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (from_currency <> to_currency),
+    UNIQUE (user_id, from_currency, to_currency, effective_date)
+);
+
+-- latest rate effective on the given day, falling back to the inverse of the
+-- opposite pair, NULL when the user never uploaded a usable rate
+CREATE FUNCTION exchange_rate(p_user_id UUID, p_from TEXT, p_to TEXT, p_on DATE)
+RETURNS DECIMAL
+LANGUAGE sql STABLE
+AS $$
+    SELECT CASE WHEN p_from = p_to THEN 1 ELSE (
+        SELECT rate
+        FROM (
+            SELECT rate, effective_date, 0 AS inverse
+            FROM exchange_rates
+            WHERE user_id = p_user_id
+            AND from_currency = p_from
+            AND to_currency = p_to
+            AND effective_date <= p_on
+            UNION ALL
+            SELECT ROUND(1 / rate, 10), effective_date, 1 AS inverse
+            FROM exchange_rates
+            WHERE user_id = p_user_id
+            AND from_currency = p_to
+            AND to_currency = p_from
+            AND effective_date <= p_on
+        ) rates
+        ORDER BY effective_date DESC, inverse
+        LIMIT 1
+    ) END
+$$;
@@ -0,0 +1,44 @@
+CREATE TYPE recurrence_frequency AS ENUM ('daily', 'weekly', 'monthly', 'yearly');
+
+CREATE TYPE occurrence_status AS ENUM ('posted', 'skipped');
+
+CREATE TABLE recurring_transactions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category_id UUID NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    account_id UUID REFERENCES accounts(id) ON DELETE SET NULL,
+    description TEXT,
+    amount DECIMAL(10, 2) NOT NULL,
+    currency TEXT NOT NULL CHECK (currency ~ '^[A-Z]{3}$'),
+    frequency recurrence_frequency NOT NULL,
+    repeat_every INT NOT NULL DEFAULT 1 CHECK (repeat_every > 0),
+    -- monthly series only, clamped to the last day of shorter months
+    day_of_month SMALLINT CHECK (day_of_month BETWEEN 1 AND 31),
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ,
+    occurrence_limit INT CHECK (occurrence_limit > 0),
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    -- index of the first occurrence the scheduler has not handled yet and when it is due,
+    -- NULL once the series is over
+    next_occurrence INT NOT NULL DEFAULT 0,
+    next_due_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (day_of_month IS NULL OR frequency = 'monthly'),
+    CHECK (ends_at IS NULL OR ends_at >= starts_at)
+);
+
+CREATE INDEX recurring_transactions_due_idx
+ON recurring_transactions(next_due_at)
+WHERE NOT paused AND next_due_at IS NOT NULL;
+
+-- one row per handled occurrence, the primary key is what makes posting idempotent
+CREATE TABLE recurring_occurrences (
+    recurring_transaction_id UUID NOT NULL REFERENCES recurring_transactions(id) ON DELETE CASCADE,
+    occurrence INT NOT NULL CHECK (occurrence >= 0),
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    status occurrence_status NOT NULL,
+    transaction_id UUID REFERENCES transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (recurring_transaction_id, occurrence)
+);
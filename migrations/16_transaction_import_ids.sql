@@ -0,0 +1,8 @@
+-- identifies where an imported transaction came from, a bank FITID or a content hash,
+-- so importing the same statement twice does not duplicate anything
+ALTER TABLE transactions
+ADD COLUMN import_id TEXT;
+
+CREATE UNIQUE INDEX transactions_import_id_idx
+ON transactions(user_id, import_id)
+WHERE import_id IS NOT NULL;
@@ -0,0 +1,15 @@
+-- similarity() for comparing descriptions of possible duplicates
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- pairs the user looked at and kept both of, stored smallest id first
+CREATE TABLE duplicate_dismissals (
+    transaction_id UUID NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    duplicate_id UUID NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (transaction_id, duplicate_id),
+    CHECK (transaction_id < duplicate_id)
+);
+
+CREATE INDEX transactions_duplicate_lookup_idx
+ON transactions(user_id, category_id, amount, transaction_date);
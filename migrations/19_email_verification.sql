@@ -0,0 +1,20 @@
+ALTER TABLE users
+ADD COLUMN email_verified_at TIMESTAMPTZ;
+
+-- accounts from before verification existed are trusted as they are
+UPDATE users SET email_verified_at = created_at;
+
+CREATE TYPE user_token_purpose AS ENUM ('verify_email', 'reset_password');
+
+-- single use tokens sent by email, only their sha256 is stored
+CREATE TABLE user_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose user_token_purpose NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX user_tokens_user_id_idx ON user_tokens(user_id, purpose);
@@ -0,0 +1,24 @@
+CREATE TYPE login_outcome AS ENUM (
+    'success',
+    'two_factor_required',
+    'invalid_credentials',
+    'invalid_two_factor',
+    'unverified_email',
+    'throttled'
+);
+
+-- every attempt at logging in, keyed by the submitted username so attempts against
+-- usernames that do not exist are throttled the same way
+CREATE TABLE login_attempts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    username TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_agent TEXT,
+    outcome login_outcome NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX login_attempts_username_idx ON login_attempts(username, created_at);
+CREATE INDEX login_attempts_ip_address_idx ON login_attempts(ip_address, created_at);
+CREATE INDEX login_attempts_user_id_idx ON login_attempts(user_id, created_at);
@@ -0,0 +1,7 @@
+-- new registrations are NFKC normalized by the application, existing rows only get
+-- their whitespace and case folded. this fails when two accounts differ only by case,
+-- those have to be merged by hand before migrating
+UPDATE users SET name = lower(btrim(name)), email = lower(btrim(email));
+
+CREATE UNIQUE INDEX users_name_lower_idx ON users (lower(name));
+CREATE UNIQUE INDEX users_email_lower_idx ON users (lower(email));
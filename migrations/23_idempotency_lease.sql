@@ -0,0 +1,5 @@
+-- a claimed key is only held until locked_until, so a request whose process died before
+-- it could store or release its response does not block retries for a whole day.
+-- requests already running when this is applied can be retried straight away
+ALTER TABLE idempotency_keys
+ADD COLUMN locked_until TIMESTAMPTZ NOT NULL DEFAULT NOW();
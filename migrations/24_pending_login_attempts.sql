@@ -0,0 +1,3 @@
+-- attempts are recorded before the password is checked and settled afterwards, so
+-- parallel attempts on one account see each other without holding a lock meanwhile
+ALTER TYPE login_outcome ADD VALUE 'pending';
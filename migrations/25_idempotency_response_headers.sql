@@ -0,0 +1,14 @@
+-- every header of a stored response as "name: value" lines, not just its content type,
+-- so a replay answers the way the first response did. hop-by-hop headers are left out
+ALTER TABLE idempotency_keys
+ADD COLUMN response_headers TEXT[];
+
+UPDATE idempotency_keys
+SET response_headers = CASE
+    WHEN content_type IS NULL THEN '{}'
+    ELSE ARRAY['content-type: ' || content_type]
+END
+WHERE status_code IS NOT NULL;
+
+ALTER TABLE idempotency_keys
+DROP COLUMN content_type;
@@ -1,21 +1,222 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    extract::{
+        multipart::{MultipartError, MultipartRejection},
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+    },
+    http::{HeaderValue, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Serialize;
 
-pub struct GlobalAppError {
-    error_code: StatusCode,
-    message: String,
+use crate::middlewares::request_id::current_request_id;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    /// machine readable reason, for fields that can be rejected in more than one way
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            code: None,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn with_code(field: &str, code: &'static str, message: &str) -> Self {
+        Self {
+            code: Some(code),
+            ..Self::new(field, message)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GlobalAppError {
+    BadRequest(String),
+    Validation(Vec<FieldError>),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Unprocessable(String),
+    RateLimited {
+        message: String,
+        retry_after_secs: u64,
+    },
+    Internal(String),
+}
+
+/// RFC 7807 body, `code` and `errors` are extension members
+#[derive(Serialize)]
+struct ProblemDetails {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl GlobalAppError {
+    pub fn validation(field: &str, message: &str) -> Self {
+        Self::Validation(vec![FieldError::new(field, message)])
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// stable machine readable code, clients should match on this instead of the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "bad_request",
+            Self::Validation(_) => "validation",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::NotFound(_) => "not_found",
+            Self::Conflict(_) => "conflict",
+            Self::Unprocessable(_) => "unprocessable",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::Validation(errors) => match errors.as_slice() {
+                [error] => format!("{}: {}", error.field, error.message),
+                _ => "request validation failed!".to_string(),
+            },
+            Self::BadRequest(message)
+            | Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::NotFound(message)
+            | Self::Conflict(message)
+            | Self::Unprocessable(message)
+            | Self::RateLimited { message, .. }
+            | Self::Internal(message) => message.clone(),
+        }
+    }
 }
 
 impl IntoResponse for GlobalAppError {
     fn into_response(self) -> axum::response::Response {
-        (self.error_code, self.message).into_response()
+        let status = self.status_code();
+        let problem = ProblemDetails {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: self.message(),
+            code: self.code(),
+            request_id: current_request_id(),
+            errors: match &self {
+                Self::Validation(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        };
+
+        let mut response = (status, Json(problem)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        if let Self::RateLimited {
+            retry_after_secs, ..
+        } = self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
     }
 }
 
-impl GlobalAppError {
-    pub fn new(error_code: StatusCode, message: String) -> Self {
-        Self {
-            error_code,
-            message,
+impl From<sqlx::Error> for GlobalAppError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => Self::NotFound("resource not found!".to_string()),
+            sqlx::Error::Database(db_error) => match db_error.kind() {
+                sqlx::error::ErrorKind::UniqueViolation => {
+                    Self::Conflict("resource already exists!".to_string())
+                }
+                sqlx::error::ErrorKind::ForeignKeyViolation => {
+                    Self::Unprocessable("referenced resource does not exist!".to_string())
+                }
+                sqlx::error::ErrorKind::CheckViolation
+                | sqlx::error::ErrorKind::NotNullViolation => {
+                    Self::Unprocessable("value violates a database constraint!".to_string())
+                }
+                _ => {
+                    eprintln!("{}", error);
+                    Self::Internal("database error!".to_string())
+                }
+            },
+            _ => {
+                eprintln!("{}", error);
+                Self::Internal("database error!".to_string())
+            }
+        }
+    }
+}
+
+impl From<JsonRejection> for GlobalAppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(error) => {
+                Self::Validation(vec![FieldError::new("body", &error.body_text())])
+            }
+            _ => Self::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for GlobalAppError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for GlobalAppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::Validation(vec![FieldError::new("query", &rejection.body_text())])
+    }
+}
+
+impl From<MultipartRejection> for GlobalAppError {
+    fn from(rejection: MultipartRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<MultipartError> for GlobalAppError {
+    fn from(error: MultipartError) -> Self {
+        match error.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => {
+                Self::Validation(vec![FieldError::new("file", &error.body_text())])
+            }
+            _ => Self::BadRequest(error.body_text()),
         }
     }
 }
@@ -0,0 +1,30 @@
+use axum::extract::{FromRequest, FromRequestParts, Multipart, Request};
+
+use crate::errors::GlobalAppError;
+
+/// `axum::Json` that rejects with a problem+json `GlobalAppError` instead of plain text
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(GlobalAppError))]
+pub struct AppJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(GlobalAppError))]
+pub struct AppPath<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(GlobalAppError))]
+pub struct AppQuery<T>(pub T);
+
+/// `axum::extract::Multipart` with the same problem+json rejections as the other extractors
+pub struct AppMultipart(pub Multipart);
+
+impl<S> FromRequest<S> for AppMultipart
+where
+    S: Send + Sync,
+{
+    type Rejection = GlobalAppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(Multipart::from_request(req, state).await?))
+    }
+}
@@ -0,0 +1,203 @@
+use axum::{Extension, Json, extract::State};
+use slug::slugify;
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    extractors::{AppJson, AppPath},
+    helpers::currencies::{ACCOUNT_TRANSACTIONS, ensure_exchange_rates, exchange_rate_join},
+    middlewares::GlobalAppState,
+    models::accounts::{AccountBalance, CreateAccount, PatchAccount},
+};
+
+/// expects `ensure_exchange_rates` to have passed, transactions without a rate would be
+/// left out of the totals
+const ACCOUNT_BALANCE_SELECT: &str = concat!(
+    r#"SELECT
+        a.id,
+        a.name,
+        a.type,
+        a.opening_balance,
+        a.created_at,
+        totals.total_inflow,
+        totals.total_outflow,
+        a.opening_balance + totals.total_inflow - totals.total_outflow AS balance,
+        totals.transaction_count
+        FROM accounts a
+        CROSS JOIN LATERAL (
+            SELECT
+            ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (
+                WHERE c.type = 'income' OR t.transfer_direction = 'incoming'
+            ), 0), 2) AS total_inflow,
+            ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (
+                WHERE c.type = 'expense' OR t.transfer_direction = 'outgoing'
+            ), 0), 2) AS total_outflow,
+            COUNT(t.id) AS transaction_count
+            FROM transactions t
+            LEFT JOIN categories c
+            ON t.category_id = c.id"#,
+    exchange_rate_join!(),
+    r#"
+            WHERE t.account_id = a.id
+        ) totals"#
+);
+
+fn account_conflict(error: sqlx::Error) -> GlobalAppError {
+    match &error {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            GlobalAppError::Conflict("an account with this name already exists!".to_string())
+        }
+        _ => error.into(),
+    }
+}
+
+fn account_slug(name: &str) -> Result<String, GlobalAppError> {
+    let slug = slugify(name);
+    if slug.is_empty() {
+        return Err(GlobalAppError::validation(
+            "name",
+            "name must contain at least one letter or digit!",
+        ));
+    }
+    Ok(slug)
+}
+
+async fn fetch_account(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_id: Uuid,
+) -> Result<AccountBalance, GlobalAppError> {
+    ensure_exchange_rates(&mut *conn, user_id, ACCOUNT_TRANSACTIONS, None, None).await?;
+
+    query_as::<_, AccountBalance>(&format!(
+        "{ACCOUNT_BALANCE_SELECT} WHERE a.id = $1 AND a.user_id = $2"
+    ))
+    .bind(account_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("account not found!".to_string()))
+}
+
+pub async fn create_account(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(account): AppJson<CreateAccount>,
+) -> Result<Json<AccountBalance>, GlobalAppError> {
+    let name = account.name.trim().to_string();
+    let slug = account_slug(&name)?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let (account_id,) = query_as::<_, (Uuid,)>(
+        "INSERT INTO accounts (user_id, name, slug, type, opening_balance) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+    )
+    .bind(uuid)
+    .bind(name)
+    .bind(slug)
+    .bind(account.account_type)
+    .bind(account.opening_balance)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(account_conflict)?;
+
+    let account = fetch_account(&mut tx, uuid, account_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(account))
+}
+
+pub async fn list_accounts(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Json<Vec<AccountBalance>>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+    ensure_exchange_rates(&mut conn, uuid, ACCOUNT_TRANSACTIONS, None, None).await?;
+
+    Ok(Json(
+        query_as::<_, AccountBalance>(&format!(
+            "{ACCOUNT_BALANCE_SELECT} WHERE a.user_id = $1 ORDER BY a.name"
+        ))
+        .bind(uuid)
+        .fetch_all(&mut *conn)
+        .await?,
+    ))
+}
+
+pub async fn display_account(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(account_id): AppPath<Uuid>,
+) -> Result<Json<AccountBalance>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(fetch_account(&mut conn, uuid, account_id).await?))
+}
+
+pub async fn update_account(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(account_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<PatchAccount>,
+) -> Result<Json<AccountBalance>, GlobalAppError> {
+    if patch.name.is_none() && patch.account_type.is_none() && patch.opening_balance.is_none() {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+
+    let name = patch.name.map(|name| name.trim().to_string());
+    let slug = name.as_deref().map(account_slug).transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let result = query(
+        r#"UPDATE accounts SET
+        name = COALESCE($1, name),
+        slug = COALESCE($2, slug),
+        type = COALESCE($3, type),
+        opening_balance = COALESCE($4, opening_balance)
+        WHERE id = $5 AND user_id = $6"#,
+    )
+    .bind(name)
+    .bind(slug)
+    .bind(patch.account_type)
+    .bind(patch.opening_balance)
+    .bind(account_id)
+    .bind(uuid)
+    .execute(&mut *tx)
+    .await
+    .map_err(account_conflict)?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound("account not found!".to_string()));
+    }
+
+    let account = fetch_account(&mut tx, uuid, account_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(account))
+}
+
+/// transactions of a deleted account are kept, they just lose their account
+pub async fn delete_account(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(account_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let result = query("DELETE FROM accounts WHERE id = $1 AND user_id = $2")
+        .bind(account_id)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound("account not found!".to_string()));
+    }
+
+    Ok("Account deleted successfully!".to_string())
+}
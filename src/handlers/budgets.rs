@@ -0,0 +1,302 @@
+use axum::{Extension, Json, extract::State};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    extractors::{AppJson, AppPath, AppQuery},
+    helpers::{
+        categories::category_by_slug,
+        currencies::{BUDGETED_TRANSACTIONS, EXCHANGE_RATE_JOIN, ensure_exchange_rates},
+    },
+    middlewares::GlobalAppState,
+    models::{
+        budgets::{
+            BudgetInfo, BudgetStatus, BudgetStatusParams, BudgetStatusRow, CreateBudget,
+            PatchBudget,
+        },
+        categories::CategoryType,
+    },
+};
+
+const BUDGET_INFO_SELECT: &str = r#"SELECT
+        b.id AS budget_id,
+        b.amount,
+        b.period,
+        b.rollover,
+        b.starts_at,
+        b.updated_at,
+        c.id,
+        c.name,
+        c.type,
+        c.is_savings,
+        c.created_at
+        FROM budgets b
+        INNER JOIN categories c
+        ON b.category_id = c.id"#;
+
+fn budget_conflict(error: sqlx::Error) -> GlobalAppError {
+    match &error {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            GlobalAppError::Conflict(
+                "a budget for this category and period already exists!".to_string(),
+            )
+        }
+        _ => error.into(),
+    }
+}
+
+fn validate_amount(amount: Decimal) -> Result<(), GlobalAppError> {
+    if amount <= Decimal::ZERO {
+        return Err(GlobalAppError::validation(
+            "amount",
+            "amount must be greater than zero!",
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_budget(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    budget_id: Uuid,
+) -> Result<BudgetInfo, GlobalAppError> {
+    query_as::<_, BudgetInfo>(&format!(
+        "{BUDGET_INFO_SELECT} WHERE b.id = $1 AND b.user_id = $2"
+    ))
+    .bind(budget_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("budget not found!".to_string()))
+}
+
+pub async fn create_budget(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(budget): AppJson<CreateBudget>,
+) -> Result<Json<BudgetInfo>, GlobalAppError> {
+    validate_amount(budget.amount)?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let category = category_by_slug(&mut tx, uuid, &budget.category).await?;
+    // read again under a lock held until commit, so `update_category` cannot turn it
+    // into an income category before the budget is in
+    let (category_type,) =
+        query_as::<_, (CategoryType,)>("SELECT type FROM categories WHERE id = $1 FOR SHARE")
+            .bind(category.id)
+            .fetch_one(&mut *tx)
+            .await?;
+    if category_type != CategoryType::Expense {
+        return Err(GlobalAppError::validation(
+            "category",
+            "budgets can only be set on expense categories!",
+        ));
+    }
+
+    let (budget_id,) = query_as::<_, (Uuid,)>(
+        r#"INSERT INTO budgets (user_id, category_id, amount, period, rollover, starts_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id"#,
+    )
+    .bind(uuid)
+    .bind(category.id)
+    .bind(budget.amount)
+    .bind(budget.period)
+    .bind(budget.rollover)
+    .bind(budget.starts_at.unwrap_or_else(Utc::now))
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(budget_conflict)?;
+
+    let budget = fetch_budget(&mut tx, uuid, budget_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(budget))
+}
+
+pub async fn list_budgets(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Json<Vec<BudgetInfo>>, GlobalAppError> {
+    Ok(Json(
+        query_as::<_, BudgetInfo>(&format!(
+            "{BUDGET_INFO_SELECT} WHERE b.user_id = $1 ORDER BY c.name, b.period"
+        ))
+        .bind(uuid)
+        .fetch_all(&state.pool)
+        .await?,
+    ))
+}
+
+pub async fn display_budget(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(budget_id): AppPath<Uuid>,
+) -> Result<Json<BudgetInfo>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(fetch_budget(&mut conn, uuid, budget_id).await?))
+}
+
+pub async fn update_budget(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(budget_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<PatchBudget>,
+) -> Result<Json<BudgetInfo>, GlobalAppError> {
+    if patch.amount.is_none() && patch.period.is_none() && patch.rollover.is_none() {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+    if let Some(amount) = patch.amount {
+        validate_amount(amount)?;
+    }
+
+    let mut tx = state.pool.begin().await?;
+
+    let result = query(
+        r#"UPDATE budgets SET
+        amount = COALESCE($1, amount),
+        period = COALESCE($2, period),
+        rollover = COALESCE($3, rollover),
+        updated_at = NOW()
+        WHERE id = $4 AND user_id = $5"#,
+    )
+    .bind(patch.amount)
+    .bind(patch.period)
+    .bind(patch.rollover)
+    .bind(budget_id)
+    .bind(uuid)
+    .execute(&mut *tx)
+    .await
+    .map_err(budget_conflict)?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound("budget not found!".to_string()));
+    }
+
+    let budget = fetch_budget(&mut tx, uuid, budget_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(budget))
+}
+
+pub async fn delete_budget(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(budget_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let result = query("DELETE FROM budgets WHERE id = $1 AND user_id = $2")
+        .bind(budget_id)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound("budget not found!".to_string()));
+    }
+
+    Ok("Budget deleted successfully!".to_string())
+}
+
+pub async fn budget_status(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<BudgetStatusParams>,
+) -> Result<Json<Vec<BudgetStatus>>, GlobalAppError> {
+    // periods are aligned to UTC calendar boundaries, weeks start on monday.
+    // with rollover, whatever was left unspent in every earlier period since starts_at is
+    // carried forward, overspending eats into that carry but never below zero
+    let mut conn = state.pool.acquire().await?;
+    ensure_exchange_rates(&mut conn, uuid, BUDGETED_TRANSACTIONS, None, None).await?;
+
+    let rows = query_as::<_, BudgetStatusRow>(&format!(
+        r#"SELECT
+        b.id AS budget_id,
+        b.amount,
+        b.period,
+        b.rollover,
+        b.starts_at,
+        b.updated_at,
+        c.id,
+        c.name,
+        c.type,
+        c.is_savings,
+        c.created_at,
+        bounds.period_start AT TIME ZONE 'UTC' AS period_start,
+        (bounds.period_start + bounds.step) AT TIME ZONE 'UTC' AS period_end,
+        ROUND(COALESCE(current.spent, 0), 2) AS spent,
+        CASE WHEN b.rollover THEN ROUND(GREATEST(0, COALESCE((
+            SELECT COUNT(*) * b.amount - COALESCE(SUM(previous.spent), 0)
+            FROM generate_series(
+                date_trunc(bounds.unit, b.starts_at AT TIME ZONE 'UTC'),
+                bounds.period_start - bounds.step,
+                bounds.step
+            ) AS p(period_start)
+            LEFT JOIN LATERAL (
+                SELECT SUM(t.amount * fx.rate) AS spent
+                FROM transactions t{EXCHANGE_RATE_JOIN}
+                WHERE t.user_id = b.user_id
+                AND t.category_id = b.category_id
+                AND t.transaction_date >= p.period_start AT TIME ZONE 'UTC'
+                AND t.transaction_date < (p.period_start + bounds.step) AT TIME ZONE 'UTC'
+            ) previous ON TRUE
+        ), 0)), 2) ELSE 0 END AS rolled_over
+        FROM budgets b
+        INNER JOIN categories c
+        ON b.category_id = c.id
+        CROSS JOIN LATERAL (
+            SELECT
+            unit,
+            date_trunc(unit, $2 AT TIME ZONE 'UTC') AS period_start,
+            ('1 ' || unit)::interval AS step
+            FROM (
+                SELECT CASE b.period
+                    WHEN 'weekly' THEN 'week'
+                    WHEN 'monthly' THEN 'month'
+                    ELSE 'year'
+                END AS unit
+            ) units
+        ) bounds
+        CROSS JOIN LATERAL (
+            SELECT SUM(t.amount * fx.rate) AS spent
+            FROM transactions t{EXCHANGE_RATE_JOIN}
+            WHERE t.user_id = b.user_id
+            AND t.category_id = b.category_id
+            AND t.transaction_date >= bounds.period_start AT TIME ZONE 'UTC'
+            AND t.transaction_date < (bounds.period_start + bounds.step) AT TIME ZONE 'UTC'
+        ) current
+        WHERE b.user_id = $1
+        ORDER BY c.name, b.period"#
+    ))
+    .bind(uuid)
+    .bind(params.at.unwrap_or_else(Utc::now))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| {
+                let available = row.budget.amount + row.rolled_over;
+                BudgetStatus {
+                    period_start: row.period_start,
+                    period_end: row.period_end,
+                    rolled_over: row.rolled_over,
+                    available,
+                    spent: row.spent,
+                    remaining: available - row.spent,
+                    percent_used: (row.spent / available * Decimal::ONE_HUNDRED).round_dp(2),
+                    budget: row.budget,
+                }
+            })
+            .collect(),
+    ))
+}
@@ -1,58 +1,137 @@
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Extension, Json,
-};
+use axum::{Extension, Json, extract::State};
 use slug::slugify;
 use sqlx::{query, query_as};
 use uuid::Uuid;
 
 use crate::{
-    errors::GlobalAppError,
+    errors::{FieldError, GlobalAppError},
+    extractors::{AppJson, AppPath, AppQuery},
     middlewares::GlobalAppState,
-    models::categories::{CreateCategoryDetails, GetUserCategories},
+    models::categories::{
+        CategoryCreateResult, CategoryCreateStatus, CategoryType, CreateCategoriesResponse,
+        CreateCategoryDetails, CreateCategoryParams, GetUserCategories, OnConflict,
+        PatchUserCategories,
+    },
 };
 
-// todo : disallow adding same categories multiple times
 pub async fn create_category(
     State(state): State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-    Json(categories): Json<Vec<CreateCategoryDetails>>,
-) -> Result<String, GlobalAppError> {
-    let mut tx = state.pool.begin().await.map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "database error!".to_string(),
+    AppQuery(params): AppQuery<CreateCategoryParams>,
+    AppJson(categories): AppJson<Vec<CreateCategoryDetails>>,
+) -> Result<Json<CreateCategoriesResponse>, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+    let mut results = Vec::with_capacity(categories.len());
+
+    for (index, category) in categories.into_iter().enumerate() {
+        let name = category.name.trim().to_string();
+        let slug = slugify(&name);
+
+        if slug.is_empty() {
+            results.push(CategoryCreateResult {
+                index,
+                name,
+                slug,
+                status: CategoryCreateStatus::Invalid,
+                id: None,
+                message: Some("name must contain at least one letter or digit!".to_string()),
+            });
+            continue;
+        }
+
+        // duplicates within the same batch also land here because of the unique (user_id, slug)
+        let inserted = query_as::<_, (Uuid,)>(
+            r#"INSERT INTO categories (user_id, name, slug, type, is_savings)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_id, slug) DO NOTHING
+            RETURNING id"#,
         )
-    })?;
-
-    for category in categories {
-        let slug = slugify(category.name.clone());
-
-        query("INSERT INTO categories (user_id, name, slug, type, is_savings) VALUES ($1, $2, $3, $4, $5)")
-            .bind(uuid)
-            .bind(category.name)
-            .bind(slug)
-            .bind(category.category_type)
-            .bind(category.is_savings)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
+        .bind(uuid)
+        .bind(&name)
+        .bind(&slug)
+        .bind(category.category_type)
+        .bind(category.is_savings)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let result = match inserted {
+            Some((id,)) => CategoryCreateResult {
+                index,
+                name,
+                slug,
+                status: CategoryCreateStatus::Created,
+                id: Some(id),
+                message: None,
+            },
+            None => {
+                let existing = query_as::<_, (Uuid,)>(
+                    "SELECT id FROM categories WHERE user_id = $1 AND slug = $2",
                 )
-            })?;
+                .bind(uuid)
+                .bind(&slug)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                CategoryCreateResult {
+                    index,
+                    name,
+                    slug,
+                    status: CategoryCreateStatus::AlreadyExists,
+                    id: Some(existing.0),
+                    message: Some("a category with this name already exists!".to_string()),
+                }
+            }
+        };
+        results.push(result);
     }
 
-    tx.commit().await.map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "database error!".to_string(),
-        )
-    })?;
+    let count = |status| {
+        results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    };
+    let created = count(CategoryCreateStatus::Created);
+    let already_existing = count(CategoryCreateStatus::AlreadyExists);
+    let invalid = count(CategoryCreateStatus::Invalid);
 
-    Ok("Categories inserted successfully!".to_string())
+    if params.on_conflict == OnConflict::Error {
+        // dropping the transaction rolls back everything inserted so far
+        if invalid > 0 {
+            return Err(GlobalAppError::Validation(
+                results
+                    .iter()
+                    .filter(|result| result.status == CategoryCreateStatus::Invalid)
+                    .map(|result| {
+                        FieldError::new(
+                            &format!("[{}].name", result.index),
+                            result.message.as_deref().unwrap_or_default(),
+                        )
+                    })
+                    .collect(),
+            ));
+        }
+        if already_existing > 0 {
+            let names: Vec<&str> = results
+                .iter()
+                .filter(|result| result.status == CategoryCreateStatus::AlreadyExists)
+                .map(|result| result.name.as_str())
+                .collect();
+            return Err(GlobalAppError::Conflict(format!(
+                "categories already exist: {}",
+                names.join(", ")
+            )));
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(CreateCategoriesResponse {
+        created,
+        already_existing,
+        invalid,
+        results,
+    }))
 }
 
 pub async fn list_categories(
@@ -65,36 +144,23 @@ pub async fn list_categories(
         )
         .bind(uuid)
         .fetch_all(&state.pool)
-        .await
-        .map_err(|_| {
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            )
-        })?,
+        .await?,
     ))
 }
 
 pub async fn delete_category(
     State(state): State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-    Path(cat_id): Path<Uuid>,
+    AppPath(cat_id): AppPath<Uuid>,
 ) -> Result<String, GlobalAppError> {
     let result = query("DELETE from categories WHERE id = $1 AND user_id = $2")
         .bind(cat_id)
         .bind(uuid)
         .execute(&state.pool)
-        .await
-        .map_err(|_| {
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            )
-        })?;
+        .await?;
 
     if result.rows_affected() == 0 {
-        return Err(GlobalAppError::new(
-            StatusCode::NOT_FOUND,
+        return Err(GlobalAppError::NotFound(
             "category was not found!".to_string(),
         ));
     }
@@ -104,7 +170,7 @@ pub async fn delete_category(
 
 pub async fn display_category(
     State(state): State<GlobalAppState>,
-    Path(cat_id): Path<Uuid>,
+    AppPath(cat_id): AppPath<Uuid>,
     Extension(uuid): Extension<Uuid>,
 ) -> Result<Json<GetUserCategories>, GlobalAppError> {
     if let Some(category) = query_as::<_, GetUserCategories>(
@@ -113,18 +179,97 @@ pub async fn display_category(
     .bind(cat_id)
     .bind(uuid)
     .fetch_optional(&state.pool)
-    .await
-    .map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "database error!".to_string(),
-        )
-    })? {
+    .await?
+    {
         Ok(Json(category))
     } else {
-        Err(GlobalAppError::new(
-            StatusCode::NOT_FOUND,
-            "category not found!".to_string(),
-        ))
+        Err(GlobalAppError::NotFound("category not found!".to_string()))
     }
 }
+
+pub async fn update_category(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(cat_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<PatchUserCategories>,
+) -> Result<Json<GetUserCategories>, GlobalAppError> {
+    if patch.name.is_none() && patch.category_type.is_none() && patch.is_savings.is_none() {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+
+    let name = patch.name.map(|name| name.trim().to_string());
+    let slug = match &name {
+        Some(name) => {
+            let slug = slugify(name);
+            if slug.is_empty() {
+                return Err(GlobalAppError::validation(
+                    "name",
+                    "name must contain at least one letter or digit!",
+                ));
+            }
+            Some(slug)
+        }
+        None => None,
+    };
+
+    let mut tx = state.pool.begin().await?;
+
+    if let Some(slug) = &slug {
+        let collision = query_as::<_, (Uuid,)>(
+            "SELECT id FROM categories WHERE user_id = $1 AND slug = $2 AND id <> $3",
+        )
+        .bind(uuid)
+        .bind(slug)
+        .bind(cat_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if collision.is_some() {
+            return Err(GlobalAppError::Conflict(
+                "another category with this name already exists!".to_string(),
+            ));
+        }
+    }
+
+    let category = query_as::<_, GetUserCategories>(
+        r#"UPDATE categories SET
+        name = COALESCE($1, name),
+        slug = COALESCE($2, slug),
+        type = COALESCE($3, type),
+        is_savings = COALESCE($4, is_savings)
+        WHERE id = $5 AND user_id = $6
+        RETURNING id, name, created_at, type, is_savings"#,
+    )
+    .bind(name)
+    .bind(slug)
+    .bind(patch.category_type)
+    .bind(patch.is_savings)
+    .bind(cat_id)
+    .bind(uuid)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("category not found!".to_string()))?;
+
+    // budgets only go on expense categories. checked after the update, whose row lock
+    // keeps `create_budget` from adding one in between
+    if patch.category_type.is_some() && category.category_type != CategoryType::Expense {
+        let (budgeted,) =
+            query_as::<_, (bool,)>("SELECT EXISTS (SELECT 1 FROM budgets WHERE category_id = $1)")
+                .bind(cat_id)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        if budgeted {
+            return Err(GlobalAppError::Conflict(
+                "category has budgets! delete them before making it an income category".to_string(),
+            ));
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(category))
+}
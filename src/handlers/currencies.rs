@@ -0,0 +1,141 @@
+use axum::{Extension, Json, extract::State};
+use rust_decimal::Decimal;
+use sqlx::{Postgres, QueryBuilder, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    extractors::{AppJson, AppPath, AppQuery},
+    helpers::currencies::{normalize_currency, parse_currency},
+    middlewares::GlobalAppState,
+    models::currencies::{ExchangeRateFilters, ExchangeRateInfo, ExchangeRateRequest},
+};
+
+const EXCHANGE_RATE_INFO_SELECT: &str = r#"SELECT
+        id,
+        from_currency,
+        to_currency,
+        rate,
+        effective_date,
+        created_at
+        FROM exchange_rates"#;
+
+/// uploading a rate that already exists for the same pair and day replaces it
+pub async fn upload_exchange_rates(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(rates): AppJson<Vec<ExchangeRateRequest>>,
+) -> Result<Json<Vec<ExchangeRateInfo>>, GlobalAppError> {
+    let mut errors = Vec::new();
+    let mut parsed = Vec::with_capacity(rates.len());
+
+    for (index, rate) in rates.into_iter().enumerate() {
+        let from = normalize_currency(&rate.from_currency);
+        let to = normalize_currency(&rate.to_currency);
+
+        for (field, code, normalized) in [
+            ("from_currency", &rate.from_currency, &from),
+            ("to_currency", &rate.to_currency, &to),
+        ] {
+            if normalized.is_none() {
+                errors.push(FieldError::new(
+                    &format!("[{index}].{field}"),
+                    &format!("'{}' is not an ISO 4217 currency!", code.trim()),
+                ));
+            }
+        }
+        if rate.rate <= Decimal::ZERO {
+            errors.push(FieldError::new(
+                &format!("[{index}].rate"),
+                "rate must be greater than zero!",
+            ));
+        }
+
+        if let (Some(from), Some(to)) = (from, to) {
+            if from == to {
+                errors.push(FieldError::new(
+                    &format!("[{index}].to_currency"),
+                    "to_currency must differ from from_currency!",
+                ));
+            }
+            parsed.push((from, to, rate.rate, rate.effective_date));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+
+    let mut tx = state.pool.begin().await?;
+    let mut uploaded = Vec::with_capacity(parsed.len());
+
+    for (from, to, rate, effective_date) in parsed {
+        uploaded.push(
+            query_as::<_, ExchangeRateInfo>(
+                r#"INSERT INTO exchange_rates (user_id, from_currency, to_currency, rate, effective_date)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_id, from_currency, to_currency, effective_date)
+                DO UPDATE SET rate = EXCLUDED.rate, created_at = NOW()
+                RETURNING id, from_currency, to_currency, rate, effective_date, created_at"#,
+            )
+            .bind(uuid)
+            .bind(from)
+            .bind(to)
+            .bind(rate)
+            .bind(effective_date)
+            .fetch_one(&mut *tx)
+            .await?,
+        );
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(uploaded))
+}
+
+pub async fn list_exchange_rates(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(filters): AppQuery<ExchangeRateFilters>,
+) -> Result<Json<Vec<ExchangeRateInfo>>, GlobalAppError> {
+    let mut builder = QueryBuilder::<Postgres>::new(EXCHANGE_RATE_INFO_SELECT);
+    builder.push(" WHERE user_id = ").push_bind(uuid);
+    if let Some(from) = filters.from_currency.as_deref() {
+        builder
+            .push(" AND from_currency = ")
+            .push_bind(parse_currency("from_currency", from)?);
+    }
+    if let Some(to) = filters.to_currency.as_deref() {
+        builder
+            .push(" AND to_currency = ")
+            .push_bind(parse_currency("to_currency", to)?);
+    }
+    builder.push(" ORDER BY from_currency, to_currency, effective_date DESC");
+
+    Ok(Json(
+        builder
+            .build_query_as::<ExchangeRateInfo>()
+            .fetch_all(&state.pool)
+            .await?,
+    ))
+}
+
+pub async fn delete_exchange_rate(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(rate_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let result = query("DELETE FROM exchange_rates WHERE id = $1 AND user_id = $2")
+        .bind(rate_id)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound(
+            "exchange rate not found!".to_string(),
+        ));
+    }
+
+    Ok("Exchange rate deleted successfully!".to_string())
+}
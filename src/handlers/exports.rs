@@ -0,0 +1,79 @@
+use axum::{
+    Extension,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    extractors::AppQuery,
+    handlers::transactions::TRANSACTION_INFO_SELECT,
+    helpers::{
+        exports::{XLSX_MAX_ROWS, content_type, export_body, file_extension, reserve_export},
+        transactions::push_transaction_filters,
+    },
+    middlewares::GlobalAppState,
+    models::{
+        exports::{ExportFormat, ExportParams},
+        transactions::TransactionFilters,
+    },
+};
+
+/// every transaction matching the listing filters, oldest first, as a file download
+pub async fn export_transactions(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(filters): AppQuery<TransactionFilters>,
+    AppQuery(params): AppQuery<ExportParams>,
+) -> Result<Response, GlobalAppError> {
+    let permit = reserve_export()?;
+
+    // a sheet that cannot hold everything is refused before the download starts
+    if params.format == ExportFormat::Xlsx {
+        let mut count = QueryBuilder::<Postgres>::new(
+            "SELECT count(*) FROM (SELECT 1 FROM transactions t LEFT JOIN categories c ON t.category_id = c.id WHERE t.user_id = ",
+        );
+        count.push_bind(uuid);
+        push_transaction_filters(&mut count, &filters)?;
+        count
+            .push(" LIMIT ")
+            .push_bind(XLSX_MAX_ROWS)
+            .push(") rows");
+        let (rows,) = count
+            .build_query_as::<(i64,)>()
+            .fetch_one(&state.pool)
+            .await?;
+        if rows >= XLSX_MAX_ROWS {
+            return Err(GlobalAppError::Unprocessable(
+                "too many transactions for a spreadsheet, narrow the filters or export csv!"
+                    .to_string(),
+            ));
+        }
+    }
+
+    let mut builder = QueryBuilder::<Postgres>::new(TRANSACTION_INFO_SELECT);
+    builder.push(" WHERE t.user_id = ").push_bind(uuid);
+    // filters are checked before the download starts so bad ones still get a proper error
+    push_transaction_filters(&mut builder, &filters)?;
+    builder.push(" ORDER BY t.transaction_date, t.id");
+
+    let disposition = format!(
+        r#"attachment; filename="transactions.{}""#,
+        file_extension(params.format)
+    );
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                content_type(params.format).to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        export_body(state.pool.clone(), builder, params.format, permit),
+    )
+        .into_response())
+}
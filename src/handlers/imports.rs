@@ -0,0 +1,121 @@
+use axum::{Extension, Json, body::Bytes, extract::State};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    extractors::{AppMultipart, AppQuery},
+    helpers::{
+        imports::{import_rows, parse_csv, parse_ofx, parse_qif},
+        transactions::duplicate_window,
+    },
+    middlewares::GlobalAppState,
+    models::{
+        imports::{CsvMapping, ImportParams, ImportResponse, StatementOptions},
+        transactions::DuplicateParams,
+    },
+};
+
+/// reads the uploaded `file` and the json settings sent next to it in `settings`
+async fn read_upload<T: DeserializeOwned>(
+    AppMultipart(mut multipart): AppMultipart,
+    settings: &str,
+) -> Result<(Option<Bytes>, Option<T>), GlobalAppError> {
+    let mut file = None;
+    let mut parsed = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file") => file = Some(field.bytes().await?),
+            Some(name) if name == settings => {
+                let text = field.text().await?;
+                parsed =
+                    Some(serde_json::from_str::<T>(&text).map_err(|error| {
+                        GlobalAppError::validation(settings, &error.to_string())
+                    })?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok((file, parsed))
+}
+
+fn require_file(file: Option<Bytes>) -> Result<Bytes, GlobalAppError> {
+    file.ok_or_else(|| GlobalAppError::validation("file", "file is required!"))
+}
+
+/// multipart form with the statement in `file` and a json `CsvMapping` in `mapping`
+pub async fn import_csv(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<ImportParams>,
+    AppQuery(duplicates): AppQuery<DuplicateParams>,
+    multipart: AppMultipart,
+) -> Result<Json<ImportResponse>, GlobalAppError> {
+    let (file, mapping) = read_upload::<CsvMapping>(multipart, "mapping").await?;
+
+    let mut errors = Vec::new();
+    if file.is_none() {
+        errors.push(FieldError::new("file", "file is required!"));
+    }
+    if mapping.is_none() {
+        errors.push(FieldError::new("mapping", "mapping is required!"));
+    }
+    let (Some(file), Some(mapping)) = (file, mapping) else {
+        return Err(GlobalAppError::Validation(errors));
+    };
+
+    let rows = parse_csv(&file, &mapping)?;
+
+    let window_days = duplicate_window(duplicates.window_days)?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(
+        import_rows(&mut conn, uuid, rows, params.preview, window_days).await?,
+    ))
+}
+
+/// multipart form with an OFX or QFX statement in `file` and optional json
+/// `StatementOptions` in `options`
+pub async fn import_ofx(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<ImportParams>,
+    AppQuery(duplicates): AppQuery<DuplicateParams>,
+    multipart: AppMultipart,
+) -> Result<Json<ImportResponse>, GlobalAppError> {
+    let (file, options) = read_upload::<StatementOptions>(multipart, "options").await?;
+
+    let rows = parse_ofx(&require_file(file)?, &options.unwrap_or_default())?;
+
+    let window_days = duplicate_window(duplicates.window_days)?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(
+        import_rows(&mut conn, uuid, rows, params.preview, window_days).await?,
+    ))
+}
+
+/// multipart form with a QIF file in `file` and optional json `StatementOptions` in `options`
+pub async fn import_qif(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<ImportParams>,
+    AppQuery(duplicates): AppQuery<DuplicateParams>,
+    multipart: AppMultipart,
+) -> Result<Json<ImportResponse>, GlobalAppError> {
+    let (file, options) = read_upload::<StatementOptions>(multipart, "options").await?;
+
+    let rows = parse_qif(&require_file(file)?, &options.unwrap_or_default())?;
+
+    let window_days = duplicate_window(duplicates.window_days)?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(
+        import_rows(&mut conn, uuid, rows, params.preview, window_days).await?,
+    ))
+}
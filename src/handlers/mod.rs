@@ -1,3 +1,11 @@
+pub mod accounts;
+pub mod budgets;
 pub mod categories;
+pub mod currencies;
+pub mod exports;
+pub mod imports;
+pub mod recurring;
+pub mod reports;
 pub mod transactions;
+pub mod transfers;
 pub mod users;
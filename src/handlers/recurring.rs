@@ -0,0 +1,448 @@
+use std::collections::HashSet;
+
+use axum::{Extension, Json, extract::State};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    extractors::{AppJson, AppPath, AppQuery},
+    helpers::{
+        accounts::ensure_account_owned,
+        categories::category_id_by_slug,
+        currencies::parse_currency,
+        recurring::{
+            MAX_CATCH_UP_OCCURRENCES, RECURRING_INFO_SELECT, catch_up, occurrence_at, save_progress,
+        },
+    },
+    middlewares::GlobalAppState,
+    models::recurring::{
+        CreateRecurringTransaction, OccurrenceStatus, PatchRecurringTransaction,
+        RecurrenceFrequency, RecurrenceRule, RecurringTransactionInfo, SkipOccurrence,
+        UpcomingOccurrence, UpcomingParams,
+    },
+};
+
+/// keeps a daily series from flooding the upcoming list
+const MAX_UPCOMING_PER_SERIES: usize = 366;
+
+fn validate_rule(rule: &RecurrenceRule) -> Result<(), GlobalAppError> {
+    let mut errors = Vec::new();
+
+    if rule.interval < 1 {
+        errors.push(FieldError::new("interval", "interval must be at least 1!"));
+    }
+    if let Some(day) = rule.day_of_month {
+        if rule.frequency != RecurrenceFrequency::Monthly {
+            errors.push(FieldError::new(
+                "day_of_month",
+                "day_of_month only applies to monthly series!",
+            ));
+        } else if !(1..=31).contains(&day) {
+            errors.push(FieldError::new(
+                "day_of_month",
+                "day_of_month must be between 1 and 31!",
+            ));
+        }
+    }
+    errors.extend(validate_end(rule).err());
+
+    // everything due already is posted right away, that has to stay a bounded amount
+    if errors.is_empty()
+        && occurrence_at(rule, MAX_CATCH_UP_OCCURRENCES).is_some_and(|at| at <= Utc::now())
+    {
+        errors.push(FieldError::new(
+            "starts_at",
+            &format!(
+                "starts_at is too far in the past, at most {MAX_CATCH_UP_OCCURRENCES} occurrences are back-filled!"
+            ),
+        ));
+    }
+
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+    Ok(())
+}
+
+fn validate_end(rule: &RecurrenceRule) -> Result<(), FieldError> {
+    if rule.count.is_some_and(|count| count < 1) {
+        return Err(FieldError::new("count", "count must be at least 1!"));
+    }
+    if rule.ends_at.is_some_and(|ends_at| ends_at < rule.starts_at) {
+        return Err(FieldError::new(
+            "ends_at",
+            "ends_at must not be before starts_at!",
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_series(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    series_id: Uuid,
+    lock: bool,
+) -> Result<RecurringTransactionInfo, GlobalAppError> {
+    query_as::<_, RecurringTransactionInfo>(&format!(
+        "{RECURRING_INFO_SELECT} WHERE r.id = $1 AND r.user_id = $2{}",
+        if lock { " FOR UPDATE OF r" } else { "" }
+    ))
+    .bind(series_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("recurring transaction not found!".to_string()))
+}
+
+/// occurrences the scheduler has not reached yet, up to `until`
+async fn upcoming_for(
+    conn: &mut PgConnection,
+    series: Vec<RecurringTransactionInfo>,
+    until: DateTime<Utc>,
+) -> Result<Vec<UpcomingOccurrence>, GlobalAppError> {
+    let series: Vec<RecurringTransactionInfo> =
+        series.into_iter().filter(|series| !series.paused).collect();
+    let ids: Vec<Uuid> = series.iter().map(|series| series.id).collect();
+
+    let skipped: HashSet<(Uuid, i32)> = query_as::<_, (Uuid, i32)>(
+        r#"SELECT o.recurring_transaction_id, o.occurrence
+        FROM recurring_occurrences o
+        INNER JOIN recurring_transactions r
+        ON o.recurring_transaction_id = r.id
+        WHERE r.id = ANY($1)
+        AND o.occurrence >= r.next_occurrence
+        AND o.status = 'skipped'"#,
+    )
+    .bind(&ids)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .collect();
+
+    let mut upcoming = Vec::new();
+    for series in series {
+        let mut occurrence = series.next_occurrence;
+        while let Some(scheduled_for) = occurrence_at(&series.rule, occurrence)
+            && scheduled_for <= until
+            && ((occurrence - series.next_occurrence) as usize) < MAX_UPCOMING_PER_SERIES
+        {
+            upcoming.push(UpcomingOccurrence {
+                recurring_transaction_id: series.id,
+                occurrence,
+                scheduled_for,
+                amount: series.amount,
+                currency: series.currency.clone(),
+                description: series.description.clone(),
+                category: series.category.name.clone(),
+                skipped: skipped.contains(&(series.id, occurrence)),
+            });
+            occurrence += 1;
+        }
+    }
+    upcoming.sort_by_key(|occurrence| occurrence.scheduled_for);
+
+    Ok(upcoming)
+}
+
+/// occurrences already due when the series is created are posted by the next scheduler run
+pub async fn create_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(series): AppJson<CreateRecurringTransaction>,
+) -> Result<Json<RecurringTransactionInfo>, GlobalAppError> {
+    validate_rule(&series.rule)?;
+    let currency = series
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let category_id = category_id_by_slug(&mut tx, uuid, &series.category).await?;
+    if let Some(account_id) = series.account_id {
+        ensure_account_owned(&mut tx, uuid, account_id, "account_id").await?;
+    }
+
+    let rule = series.rule;
+    let (series_id,) = query_as::<_, (Uuid,)>(
+        r#"INSERT INTO recurring_transactions
+        (user_id, category_id, account_id, description, amount, currency, frequency,
+        repeat_every, day_of_month, starts_at, ends_at, occurrence_limit, next_due_at)
+        VALUES ($1, $2, $3, $4, $5, COALESCE($6, (SELECT base_currency FROM users WHERE id = $1)),
+        $7, $8, $9, $10, $11, $12, $13)
+        RETURNING id"#,
+    )
+    .bind(uuid)
+    .bind(category_id)
+    .bind(series.account_id)
+    .bind(series.description)
+    .bind(series.amount)
+    .bind(currency)
+    .bind(rule.frequency)
+    .bind(rule.interval)
+    .bind(rule.day_of_month)
+    .bind(rule.starts_at)
+    .bind(rule.ends_at)
+    .bind(rule.count)
+    .bind(occurrence_at(&rule, 0))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, false).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(series))
+}
+
+pub async fn list_recurring_transactions(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Json<Vec<RecurringTransactionInfo>>, GlobalAppError> {
+    Ok(Json(
+        query_as::<_, RecurringTransactionInfo>(&format!(
+            "{RECURRING_INFO_SELECT} WHERE r.user_id = $1 ORDER BY r.next_due_at NULLS LAST, r.id"
+        ))
+        .bind(uuid)
+        .fetch_all(&state.pool)
+        .await?,
+    ))
+}
+
+pub async fn display_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+) -> Result<Json<RecurringTransactionInfo>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(fetch_series(&mut conn, uuid, series_id, false).await?))
+}
+
+/// changes only apply to occurrences posted from now on
+pub async fn update_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<PatchRecurringTransaction>,
+) -> Result<Json<RecurringTransactionInfo>, GlobalAppError> {
+    if patch.amount.is_none()
+        && patch.currency.is_none()
+        && patch.description.is_none()
+        && patch.account_id.is_none()
+        && patch.ends_at.is_none()
+        && patch.count.is_none()
+    {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+    let currency = patch
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let mut series = fetch_series(&mut tx, uuid, series_id, true).await?;
+    if let Some(account_id) = patch.account_id {
+        ensure_account_owned(&mut tx, uuid, account_id, "account_id").await?;
+    }
+
+    series.rule.ends_at = patch.ends_at.or(series.rule.ends_at);
+    series.rule.count = patch.count.or(series.rule.count);
+    validate_end(&series.rule).map_err(|error| GlobalAppError::Validation(vec![error]))?;
+
+    query(
+        r#"UPDATE recurring_transactions SET
+        amount = COALESCE($1, amount),
+        currency = COALESCE($2, currency),
+        description = COALESCE($3, description),
+        account_id = COALESCE($4, account_id),
+        ends_at = $5,
+        occurrence_limit = $6,
+        updated_at = NOW()
+        WHERE id = $7"#,
+    )
+    .bind(patch.amount)
+    .bind(currency)
+    .bind(patch.description)
+    .bind(patch.account_id)
+    .bind(series.rule.ends_at)
+    .bind(series.rule.count)
+    .bind(series_id)
+    .execute(&mut *tx)
+    .await?;
+
+    // a new end can finish the series early or give it more occurrences
+    save_progress(&mut tx, series_id, &series.rule, series.next_occurrence).await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, false).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(series))
+}
+
+/// transactions that were already posted are kept
+pub async fn delete_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let result = query("DELETE FROM recurring_transactions WHERE id = $1 AND user_id = $2")
+        .bind(series_id)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound(
+            "recurring transaction not found!".to_string(),
+        ));
+    }
+
+    Ok("Recurring transaction deleted successfully!".to_string())
+}
+
+pub async fn pause_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+) -> Result<Json<RecurringTransactionInfo>, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, true).await?;
+    if series.paused {
+        return Err(GlobalAppError::Conflict(
+            "recurring transaction is already paused!".to_string(),
+        ));
+    }
+
+    query("UPDATE recurring_transactions SET paused = TRUE, updated_at = NOW() WHERE id = $1")
+        .bind(series_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, false).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(series))
+}
+
+/// occurrences that fell due while the series was paused are skipped, not back-filled
+pub async fn resume_recurring_transaction(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+) -> Result<Json<RecurringTransactionInfo>, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, true).await?;
+    if !series.paused {
+        return Err(GlobalAppError::Conflict(
+            "recurring transaction is not paused!".to_string(),
+        ));
+    }
+
+    catch_up(&mut tx, &series, Utc::now(), OccurrenceStatus::Skipped).await?;
+
+    query("UPDATE recurring_transactions SET paused = FALSE, updated_at = NOW() WHERE id = $1")
+        .bind(series_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, false).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(series))
+}
+
+pub async fn skip_occurrence(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+    AppJson(skip): AppJson<SkipOccurrence>,
+) -> Result<String, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let series = fetch_series(&mut tx, uuid, series_id, true).await?;
+    if skip.occurrence < series.next_occurrence {
+        return Err(GlobalAppError::Conflict(format!(
+            "occurrence {} has already been handled!",
+            skip.occurrence
+        )));
+    }
+    let Some(scheduled_for) = occurrence_at(&series.rule, skip.occurrence) else {
+        return Err(GlobalAppError::validation(
+            "occurrence",
+            "the series has no such occurrence!",
+        ));
+    };
+
+    let result = query(
+        r#"INSERT INTO recurring_occurrences (recurring_transaction_id, occurrence, scheduled_for, status)
+        VALUES ($1, $2, $3, 'skipped')
+        ON CONFLICT DO NOTHING"#,
+    )
+    .bind(series_id)
+    .bind(skip.occurrence)
+    .bind(scheduled_for)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::Conflict(format!(
+            "occurrence {} is already skipped!",
+            skip.occurrence
+        )));
+    }
+
+    tx.commit().await?;
+
+    Ok("Occurrence skipped successfully!".to_string())
+}
+
+pub async fn list_upcoming_occurrences(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<UpcomingParams>,
+) -> Result<Json<Vec<UpcomingOccurrence>>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    let series = query_as::<_, RecurringTransactionInfo>(&format!(
+        "{RECURRING_INFO_SELECT} WHERE r.user_id = $1"
+    ))
+    .bind(uuid)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let until = params
+        .until
+        .unwrap_or_else(|| Utc::now() + Duration::days(30));
+
+    Ok(Json(upcoming_for(&mut conn, series, until).await?))
+}
+
+pub async fn list_series_upcoming_occurrences(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(series_id): AppPath<Uuid>,
+    AppQuery(params): AppQuery<UpcomingParams>,
+) -> Result<Json<Vec<UpcomingOccurrence>>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    let series = fetch_series(&mut conn, uuid, series_id, false).await?;
+    let until = params
+        .until
+        .unwrap_or_else(|| Utc::now() + Duration::days(30));
+
+    Ok(Json(upcoming_for(&mut conn, vec![series], until).await?))
+}
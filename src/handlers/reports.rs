@@ -0,0 +1,266 @@
+use axum::{Extension, Json, extract::State};
+use rust_decimal::Decimal;
+use sqlx::query_as;
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    extractors::AppQuery,
+    helpers::currencies::{
+        CATEGORIZED_TRANSACTIONS, EXCHANGE_RATE_JOIN, base_currency, ensure_exchange_rates,
+    },
+    middlewares::GlobalAppState,
+    models::{
+        categories::CategoryType,
+        reports::{
+            CategoryBreakdown, CategoryBreakdownRow, CategoryReport, CategoryReportParams,
+            CategoryTypeBreakdown, CurrencySummary, CurrencySummaryRow, SummaryParams,
+            SummaryPeriod, SummaryPeriodRow, SummaryReport, SummaryTotals,
+        },
+    },
+};
+
+const MAX_REPORT_PERIODS: i64 = 1000;
+
+pub async fn summary_report(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<SummaryParams>,
+) -> Result<Json<SummaryReport>, GlobalAppError> {
+    if params.from > params.to {
+        return Err(GlobalAppError::validation(
+            "from",
+            "from must not be after to!",
+        ));
+    }
+    if (params.to - params.from).num_days() / params.granularity.approximate_days()
+        > MAX_REPORT_PERIODS
+    {
+        return Err(GlobalAppError::validation(
+            "granularity",
+            &format!(
+                "range spans more than {MAX_REPORT_PERIODS} periods, use a coarser granularity!"
+            ),
+        ));
+    }
+
+    let mut conn = state.pool.acquire().await?;
+    ensure_exchange_rates(
+        &mut conn,
+        uuid,
+        CATEGORIZED_TRANSACTIONS,
+        Some(params.from),
+        Some(params.to),
+    )
+    .await?;
+    let base_currency = base_currency(&mut conn, uuid).await?;
+
+    // periods are computed in UTC and empty periods are kept so charts have no gaps
+    let rows = query_as::<_, SummaryPeriodRow>(&format!(
+        r#"WITH periods AS (
+            SELECT generate_series(
+                date_trunc($1, $2 AT TIME ZONE 'UTC'),
+                date_trunc($1, $3 AT TIME ZONE 'UTC'),
+                ('1 ' || $1)::interval
+            ) AS period_start
+        ),
+        totals AS (
+            SELECT
+            date_trunc($1, t.transaction_date AT TIME ZONE 'UTC') AS period_start,
+            SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'income') AS total_income,
+            SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'expense' AND NOT c.is_savings) AS total_expense,
+            SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'expense' AND c.is_savings) AS total_savings
+            FROM transactions t
+            INNER JOIN categories c
+            ON t.category_id = c.id{EXCHANGE_RATE_JOIN}
+            WHERE t.user_id = $4
+            AND t.transaction_date >= $2
+            AND t.transaction_date <= $3
+            GROUP BY 1
+        )
+        SELECT
+        p.period_start AT TIME ZONE 'UTC' AS period_start,
+        (p.period_start + ('1 ' || $1)::interval) AT TIME ZONE 'UTC' AS period_end,
+        ROUND(COALESCE(totals.total_income, 0), 2) AS total_income,
+        ROUND(COALESCE(totals.total_expense, 0), 2) AS total_expense,
+        ROUND(COALESCE(totals.total_savings, 0), 2) AS total_savings
+        FROM periods p
+        LEFT JOIN totals
+        ON totals.period_start = p.period_start
+        ORDER BY p.period_start"#
+    ))
+    .bind(params.granularity.as_str())
+    .bind(params.from)
+    .bind(params.to)
+    .bind(uuid)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let currencies = query_as::<_, CurrencySummaryRow>(&format!(
+        r#"SELECT
+        t.currency,
+        COUNT(t.id) AS transaction_count,
+        COALESCE(SUM(t.amount) FILTER (WHERE c.type = 'income'), 0) AS total_income,
+        COALESCE(SUM(t.amount) FILTER (WHERE c.type = 'expense' AND NOT c.is_savings), 0) AS total_expense,
+        COALESCE(SUM(t.amount) FILTER (WHERE c.type = 'expense' AND c.is_savings), 0) AS total_savings,
+        ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'income'), 0), 2) AS converted_income,
+        ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'expense' AND NOT c.is_savings), 0), 2) AS converted_expense,
+        ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (WHERE c.type = 'expense' AND c.is_savings), 0), 2) AS converted_savings
+        FROM transactions t
+        INNER JOIN categories c
+        ON t.category_id = c.id{EXCHANGE_RATE_JOIN}
+        WHERE t.user_id = $1
+        AND t.transaction_date >= $2
+        AND t.transaction_date <= $3
+        GROUP BY t.currency
+        ORDER BY t.currency"#
+    ))
+    .bind(uuid)
+    .bind(params.from)
+    .bind(params.to)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let original = row.total_income + row.total_expense + row.total_savings;
+        let converted = row.converted_income + row.converted_expense + row.converted_savings;
+
+        CurrencySummary {
+            currency: row.currency,
+            transaction_count: row.transaction_count,
+            original: SummaryTotals::new(row.total_income, row.total_expense, row.total_savings),
+            converted: SummaryTotals::new(
+                row.converted_income,
+                row.converted_expense,
+                row.converted_savings,
+            ),
+            average_rate: (!original.is_zero()).then(|| (converted / original).round_dp(6)),
+        }
+    })
+    .collect();
+
+    let (mut income, mut expense, mut savings) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    let periods = rows
+        .into_iter()
+        .map(|row| {
+            income += row.total_income;
+            expense += row.total_expense;
+            savings += row.total_savings;
+
+            SummaryPeriod {
+                period_start: row.period_start,
+                period_end: row.period_end,
+                totals: SummaryTotals::new(row.total_income, row.total_expense, row.total_savings),
+            }
+        })
+        .collect();
+
+    Ok(Json(SummaryReport {
+        from: params.from,
+        to: params.to,
+        granularity: params.granularity,
+        base_currency,
+        totals: SummaryTotals::new(income, expense, savings),
+        currencies,
+        periods,
+    }))
+}
+
+fn percentage(part: Decimal, whole: Decimal) -> Option<Decimal> {
+    (!whole.is_zero()).then(|| (part / whole * Decimal::ONE_HUNDRED).round_dp(2))
+}
+
+/// shares are computed within one category type so income and expense never mix
+fn breakdown_for(
+    rows: &mut Vec<CategoryBreakdownRow>,
+    category_type: CategoryType,
+) -> CategoryTypeBreakdown {
+    let rows: Vec<CategoryBreakdownRow> = rows
+        .extract_if(.., |row| row.category.category_type == category_type)
+        .collect();
+    let total: Decimal = rows.iter().map(|row| row.total).sum();
+    let previous_total: Decimal = rows.iter().map(|row| row.previous_total).sum();
+
+    let categories = rows
+        .into_iter()
+        .map(|row| CategoryBreakdown {
+            share: percentage(row.total, total),
+            change: row.total - row.previous_total,
+            change_percent: percentage(row.total - row.previous_total, row.previous_total),
+            category: row.category,
+            total: row.total,
+            transaction_count: row.transaction_count,
+            previous_total: row.previous_total,
+        })
+        .collect();
+
+    CategoryTypeBreakdown {
+        total,
+        previous_total,
+        categories,
+    }
+}
+
+pub async fn category_report(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<CategoryReportParams>,
+) -> Result<Json<CategoryReport>, GlobalAppError> {
+    if params.from > params.to {
+        return Err(GlobalAppError::validation(
+            "from",
+            "from must not be after to!",
+        ));
+    }
+
+    // the previous period has the same length and ends right where this one starts
+    let previous_to = params.from;
+    let previous_from = params.from - (params.to - params.from);
+
+    let mut conn = state.pool.acquire().await?;
+    ensure_exchange_rates(
+        &mut conn,
+        uuid,
+        CATEGORIZED_TRANSACTIONS,
+        Some(previous_from),
+        Some(params.to),
+    )
+    .await?;
+    let base_currency = base_currency(&mut conn, uuid).await?;
+
+    let mut rows = query_as::<_, CategoryBreakdownRow>(&format!(
+        r#"SELECT
+        c.id,
+        c.name,
+        c.type,
+        c.is_savings,
+        c.created_at,
+        ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (WHERE t.transaction_date >= $2), 0), 2) AS total,
+        COUNT(t.id) FILTER (WHERE t.transaction_date >= $2) AS transaction_count,
+        ROUND(COALESCE(SUM(t.amount * fx.rate) FILTER (WHERE t.transaction_date < $2), 0), 2) AS previous_total
+        FROM categories c
+        INNER JOIN transactions t
+        ON t.category_id = c.id{EXCHANGE_RATE_JOIN}
+        WHERE c.user_id = $1
+        AND t.transaction_date >= $4
+        AND t.transaction_date <= $3
+        GROUP BY c.id
+        ORDER BY total DESC, c.name"#
+    ))
+    .bind(uuid)
+    .bind(params.from)
+    .bind(params.to)
+    .bind(previous_from)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Json(CategoryReport {
+        from: params.from,
+        to: params.to,
+        base_currency,
+        previous_from,
+        previous_to,
+        income: breakdown_for(&mut rows, CategoryType::Income),
+        expense: breakdown_for(&mut rows, CategoryType::Expense),
+    }))
+}
@@ -1,102 +1,471 @@
-use axum::{extract::State, http::StatusCode, Extension, Json};
-use slug::slugify;
-use sqlx::{query, query_as};
+use std::collections::HashMap;
+
+use axum::{Extension, Json, extract::State};
+use sqlx::{PgConnection, Postgres, QueryBuilder, query, query_as};
 use uuid::Uuid;
 
 use crate::{
     errors::GlobalAppError,
+    extractors::{AppJson, AppPath, AppQuery},
+    helpers::accounts::ensure_account_owned,
+    helpers::categories::category_id_by_slug,
+    helpers::currencies::{exchange_rate_join, parse_currency},
+    helpers::transactions::{
+        DEFAULT_PAGE_SIZE, DUPLICATE_JOIN, MAX_PAGE_SIZE, decode_cursor, duplicate_window,
+        encode_cursor, insert_transaction, possible_duplicates, push_transaction_filters,
+        push_transaction_page,
+    },
+    helpers::transfers::patch_transfer,
     middlewares::GlobalAppState,
-    models::transactions::{GetCategoryId, TransactionInfo, TransactionRequest},
+    models::transactions::{
+        AddTransactionsResponse, DuplicatePairInfo, DuplicatePairRequest, DuplicateParams,
+        DuplicateWarning, GetCategoryId, TransactionFilters, TransactionInfo, TransactionPage,
+        TransactionPageParams, TransactionPatch, TransactionRequest,
+    },
+    models::transfers::{PatchTransfer, TransferDirection},
 };
 
+pub const TRANSACTION_INFO_SELECT: &str = concat!(
+    r#"SELECT
+        t.id AS transaction_id,
+        t.amount,
+        t.currency,
+        t.description,
+        t.transaction_date,
+        t.account_id,
+        u.base_currency,
+        fx.rate AS exchange_rate,
+        ROUND(t.amount * fx.rate, 2) AS base_amount,
+        c.id AS category_id,
+        c.name,
+        c.type,
+        c.is_savings,
+        c.created_at,
+        t.transfer_id,
+        t.transfer_direction
+        FROM transactions t
+        LEFT JOIN categories c
+        ON t.category_id = c.id"#,
+    exchange_rate_join!()
+);
+
+async fn fetch_transaction(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: Uuid,
+) -> Result<TransactionInfo, GlobalAppError> {
+    query_as::<_, TransactionInfo>(&format!(
+        "{TRANSACTION_INFO_SELECT} WHERE t.id = $1 AND t.user_id = $2"
+    ))
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("transaction not found!".to_string()))
+}
+
+/// transfer the transaction is a leg of and which leg it is, if any
+async fn transfer_of(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: Uuid,
+) -> Result<Option<(Uuid, TransferDirection)>, GlobalAppError> {
+    Ok(query_as::<_, (Option<Uuid>, Option<TransferDirection>)>(
+        "SELECT transfer_id, transfer_direction FROM transactions WHERE id = $1 AND user_id = $2",
+    )
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .and_then(|(transfer_id, direction)| transfer_id.zip(direction)))
+}
+
+/// stores every transaction or none, possible duplicates of earlier transactions are
+/// stored anyway and reported as warnings
 pub async fn add_transactions(
     state: State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-    Json(transactions): Json<Vec<TransactionRequest>>,
-) -> Result<String, GlobalAppError> {
-    let mut tx = state.pool.begin().await.map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "database error!".to_string(),
-        )
-    })?;
+    AppQuery(params): AppQuery<DuplicateParams>,
+    AppJson(transactions): AppJson<Vec<TransactionRequest>>,
+) -> Result<Json<AddTransactionsResponse>, GlobalAppError> {
+    let window_days = duplicate_window(params.window_days)?;
 
-    for transaction in transactions {
-        let cat_slug = slugify(transaction.category);
+    let mut tx = state.pool.begin().await?;
 
-        let cat_id = query_as::<_, GetCategoryId>(
-            "SELECT id FROM categories WHERE slug = $1 AND user_id = $2",
-        )
-        .bind(cat_slug)
-        .bind(uuid)
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(|error| match error {
-            sqlx::Error::RowNotFound => {
-                GlobalAppError::new(StatusCode::BAD_REQUEST, "category not found!".to_string())
-            }
-            _ => GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            ),
-        })?;
-
-        query("INSERT INTO transactions (user_id, category_id, description, amount, transaction_date) VALUES ($1, $2, $3, $4, $5)")
-            .bind(uuid)
-            .bind(cat_id.id)
-            .bind(transaction.description)
-            .bind(transaction.amount)
-            .bind(transaction.transaction_date)
-            .execute(&mut *tx)
-            .await
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
-                )
-            })?;
+    let mut transaction_ids = Vec::with_capacity(transactions.len());
+    let mut warnings = Vec::new();
+    for (index, transaction) in transactions.iter().enumerate() {
+        let transaction_id = insert_transaction(&mut tx, uuid, transaction, None).await?;
+        transaction_ids.push(transaction_id);
+
+        let duplicates = possible_duplicates(&mut tx, uuid, transaction_id, window_days).await?;
+        if !duplicates.is_empty() {
+            warnings.push(DuplicateWarning {
+                index,
+                transaction_id,
+                possible_duplicates: duplicates,
+            });
+        }
     }
 
-    tx.commit().await.map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "database error!".to_string(),
-        )
-    })?;
+    tx.commit().await?;
 
-    Ok("Expenses updated successfully!".to_string())
+    Ok(Json(AddTransactionsResponse {
+        message: "Expenses updated successfully!".to_string(),
+        transaction_ids,
+        warnings,
+    }))
 }
 
 pub async fn list_transactions(
     state: State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-) -> Result<Json<Vec<TransactionInfo>>, GlobalAppError> {
+    AppQuery(filters): AppQuery<TransactionFilters>,
+    AppQuery(page): AppQuery<TransactionPageParams>,
+) -> Result<Json<TransactionPage>, GlobalAppError> {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(GlobalAppError::validation(
+            "limit",
+            &format!("limit must be between 1 and {MAX_PAGE_SIZE}!"),
+        ));
+    }
+    let cursor = page.cursor.as_deref().map(decode_cursor).transpose()?;
+
+    let mut builder = QueryBuilder::<Postgres>::new(TRANSACTION_INFO_SELECT);
+    builder.push(" WHERE t.user_id = ").push_bind(uuid);
+    push_transaction_filters(&mut builder, &filters)?;
+    // one extra row tells us whether another page follows
+    push_transaction_page(&mut builder, page.sort, page.order, cursor, limit + 1)?;
+
+    let mut transactions = builder
+        .build_query_as::<TransactionInfo>()
+        .fetch_all(&state.pool)
+        .await?;
+
+    let next_cursor = if transactions.len() as i64 > limit {
+        transactions.truncate(limit as usize);
+        transactions
+            .last()
+            .map(|last| encode_cursor(page.sort, page.order, last))
+    } else {
+        None
+    };
+
+    Ok(Json(TransactionPage {
+        transactions,
+        next_cursor,
+    }))
+}
+
+pub async fn display_transaction(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transaction_id): AppPath<Uuid>,
+) -> Result<Json<TransactionInfo>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
     Ok(Json(
-        query_as::<_, TransactionInfo>(
-            r#"SELECT 
-        t.id, 
-        t.amount, 
-        t.description, 
-        t.transaction_date, 
-        c.id, 
-        c.name, 
-        c.type, 
-        c.is_savings, 
-        c.created_at
-        FROM transactions t 
-        INNER JOIN categories c 
-        ON t.category_id = c.id 
-        WHERE t.user_id = $1"#,
+        fetch_transaction(&mut conn, uuid, transaction_id).await?,
+    ))
+}
+
+pub async fn update_transaction(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transaction_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<TransactionPatch>,
+) -> Result<Json<TransactionInfo>, GlobalAppError> {
+    if patch.amount.is_none()
+        && patch.currency.is_none()
+        && patch.description.is_none()
+        && patch.transaction_date.is_none()
+        && patch.category.is_none()
+        && patch.category_id.is_none()
+        && patch.account_id.is_none()
+    {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+
+    let currency = patch
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    // editing either leg of a transfer edits both, its account is the one on that side
+    if let Some((transfer_id, direction)) = transfer_of(&mut tx, uuid, transaction_id).await? {
+        if patch.category.is_some() || patch.category_id.is_some() {
+            return Err(GlobalAppError::validation(
+                "category",
+                "transfers have no category!",
+            ));
+        }
+
+        let (from_account_id, to_account_id) = match direction {
+            TransferDirection::Outgoing => (patch.account_id, None),
+            TransferDirection::Incoming => (None, patch.account_id),
+        };
+        patch_transfer(
+            &mut tx,
+            uuid,
+            transfer_id,
+            &PatchTransfer {
+                from_account_id,
+                to_account_id,
+                amount: patch.amount,
+                currency: patch.currency,
+                transaction_date: patch.transaction_date,
+                description: patch.description,
+            },
         )
-        .bind(uuid)
-        .fetch_all(&state.pool)
-        .await
-        .map_err(|error| {
-            eprintln!("{}", error.to_string());
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
+        .await?;
+
+        let transaction = fetch_transaction(&mut tx, uuid, transaction_id).await?;
+
+        tx.commit().await?;
+
+        return Ok(Json(transaction));
+    }
+
+    let category_id = match (patch.category, patch.category_id) {
+        (Some(_), Some(_)) => {
+            return Err(GlobalAppError::validation(
+                "category",
+                "provide either category or category_id, not both!",
+            ));
+        }
+        (Some(category), None) => Some(category_id_by_slug(&mut tx, uuid, &category).await?),
+        (None, Some(category_id)) => Some(
+            query_as::<_, GetCategoryId>(
+                "SELECT id FROM categories WHERE id = $1 AND user_id = $2",
             )
-        })?,
+            .bind(category_id)
+            .bind(uuid)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| GlobalAppError::validation("category_id", "category not found!"))?
+            .id,
+        ),
+        (None, None) => None,
+    };
+
+    if let Some(account_id) = patch.account_id {
+        ensure_account_owned(&mut tx, uuid, account_id, "account_id").await?;
+    }
+
+    let result = query(
+        r#"UPDATE transactions SET
+        amount = COALESCE($1, amount),
+        description = CASE WHEN $9 THEN $2 ELSE description END,
+        transaction_date = COALESCE($3, transaction_date),
+        category_id = COALESCE($4, category_id),
+        account_id = COALESCE($5, account_id),
+        currency = COALESCE($6, currency)
+        WHERE id = $7 AND user_id = $8"#,
+    )
+    .bind(patch.amount)
+    .bind(patch.description.as_ref().and_then(Option::as_deref))
+    .bind(patch.transaction_date)
+    .bind(category_id)
+    .bind(patch.account_id)
+    .bind(currency)
+    .bind(transaction_id)
+    .bind(uuid)
+    .bind(patch.description.is_some())
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound(
+            "transaction not found!".to_string(),
+        ));
+    }
+
+    let transaction = fetch_transaction(&mut tx, uuid, transaction_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(transaction))
+}
+
+pub async fn delete_transaction(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transaction_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    // deleting either leg of a transfer removes the whole pair
+    if let Some((transfer_id, _)) = transfer_of(&mut conn, uuid, transaction_id).await? {
+        query("DELETE FROM transfers WHERE id = $1 AND user_id = $2")
+            .bind(transfer_id)
+            .bind(uuid)
+            .execute(&mut *conn)
+            .await?;
+
+        return Ok("Transfer deleted successfully!".to_string());
+    }
+
+    let result = query("DELETE FROM transactions WHERE id = $1 AND user_id = $2")
+        .bind(transaction_id)
+        .bind(uuid)
+        .execute(&mut *conn)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound(
+            "transaction not found!".to_string(),
+        ));
+    }
+
+    Ok("Transaction deleted successfully!".to_string())
+}
+
+/// every pair of transactions that look like the same one, newest first
+pub async fn list_duplicates(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppQuery(params): AppQuery<DuplicateParams>,
+) -> Result<Json<Vec<DuplicatePairInfo>>, GlobalAppError> {
+    let window_days = duplicate_window(params.window_days)?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    let pairs = query_as::<_, (Uuid, Uuid)>(&format!(
+        r#"SELECT a.id, b.id FROM transactions a
+        {DUPLICATE_JOIN}
+        WHERE a.user_id = $1 AND a.id < b.id
+        ORDER BY GREATEST(a.transaction_date, b.transaction_date) DESC, a.id, b.id"#
     ))
+    .bind(uuid)
+    .bind(window_days)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let ids: Vec<Uuid> = pairs.iter().flat_map(|(a, b)| [*a, *b]).collect();
+    let transactions: HashMap<Uuid, TransactionInfo> = query_as::<_, TransactionInfo>(&format!(
+        "{TRANSACTION_INFO_SELECT} WHERE t.user_id = $1 AND t.id = ANY($2)"
+    ))
+    .bind(uuid)
+    .bind(&ids)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|transaction| (transaction.id, transaction))
+    .collect();
+
+    Ok(Json(
+        pairs
+            .into_iter()
+            .filter_map(|(a, b)| {
+                Some(DuplicatePairInfo {
+                    transaction: transactions.get(&a)?.clone(),
+                    duplicate: transactions.get(&b)?.clone(),
+                })
+            })
+            .collect(),
+    ))
+}
+
+fn ensure_distinct(pair: &DuplicatePairRequest) -> Result<(), GlobalAppError> {
+    if pair.transaction_id == pair.duplicate_id {
+        return Err(GlobalAppError::validation(
+            "duplicate_id",
+            "duplicate_id must differ from transaction_id!",
+        ));
+    }
+
+    Ok(())
+}
+
+/// keeps `transaction_id` and deletes `duplicate_id`, details only the duplicate has are
+/// carried over, including its import id so importing the same statement again does not
+/// bring the duplicate back
+pub async fn merge_duplicates(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(pair): AppJson<DuplicatePairRequest>,
+) -> Result<Json<TransactionInfo>, GlobalAppError> {
+    ensure_distinct(&pair)?;
+
+    let mut tx = state.pool.begin().await?;
+
+    for id in [pair.transaction_id, pair.duplicate_id] {
+        if let Some(transfer_id) = fetch_transaction(&mut tx, uuid, id).await?.transfer_id {
+            return Err(GlobalAppError::Conflict(format!(
+                "transaction is one leg of transfer {transfer_id}, transfers cannot be merged!"
+            )));
+        }
+    }
+
+    // a recurring series that posted the duplicate now points at the one that was kept
+    query("UPDATE recurring_occurrences SET transaction_id = $1 WHERE transaction_id = $2")
+        .bind(pair.transaction_id)
+        .bind(pair.duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let (description, account_id, import_id) =
+        query_as::<_, (Option<String>, Option<Uuid>, Option<String>)>(
+            "DELETE FROM transactions WHERE id = $1 AND user_id = $2 RETURNING description, account_id, import_id",
+        )
+        .bind(pair.duplicate_id)
+        .bind(uuid)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    query(
+        r#"UPDATE transactions SET
+        description = COALESCE(NULLIF(description, ''), $1),
+        account_id = COALESCE(account_id, $2),
+        import_id = COALESCE(import_id, $3)
+        WHERE id = $4 AND user_id = $5"#,
+    )
+    .bind(description)
+    .bind(account_id)
+    .bind(import_id)
+    .bind(pair.transaction_id)
+    .bind(uuid)
+    .execute(&mut *tx)
+    .await?;
+
+    let transaction = fetch_transaction(&mut tx, uuid, pair.transaction_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(transaction))
+}
+
+/// remembers that the two transactions are not the same, so they are no longer reported
+pub async fn dismiss_duplicates(
+    state: State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(pair): AppJson<DuplicatePairRequest>,
+) -> Result<String, GlobalAppError> {
+    ensure_distinct(&pair)?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    for id in [pair.transaction_id, pair.duplicate_id] {
+        fetch_transaction(&mut conn, uuid, id).await?;
+    }
+
+    query(
+        r#"INSERT INTO duplicate_dismissals (transaction_id, duplicate_id, user_id)
+        VALUES (LEAST($1, $2), GREATEST($1, $2), $3)
+        ON CONFLICT DO NOTHING"#,
+    )
+    .bind(pair.transaction_id)
+    .bind(pair.duplicate_id)
+    .bind(uuid)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok("Duplicate dismissed successfully!".to_string())
 }
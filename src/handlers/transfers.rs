@@ -0,0 +1,139 @@
+use axum::{Extension, Json, extract::State};
+use sqlx::{query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    extractors::{AppJson, AppPath},
+    helpers::accounts::ensure_account_owned,
+    helpers::currencies::parse_currency,
+    helpers::transfers::{
+        TRANSFER_INFO_SELECT, fetch_transfer, patch_transfer, validate_accounts, validate_amount,
+    },
+    middlewares::GlobalAppState,
+    models::transfers::{CreateTransfer, PatchTransfer, TransferDirection, TransferInfo},
+};
+
+pub async fn create_transfer(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(transfer): AppJson<CreateTransfer>,
+) -> Result<Json<TransferInfo>, GlobalAppError> {
+    validate_amount(transfer.amount)?;
+    validate_accounts(Some(transfer.from_account_id), Some(transfer.to_account_id))?;
+    let currency = transfer
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let mut tx = state.pool.begin().await?;
+
+    ensure_account_owned(&mut tx, uuid, transfer.from_account_id, "from_account_id").await?;
+    ensure_account_owned(&mut tx, uuid, transfer.to_account_id, "to_account_id").await?;
+
+    let (transfer_id,) =
+        query_as::<_, (Uuid,)>("INSERT INTO transfers (user_id) VALUES ($1) RETURNING id")
+            .bind(uuid)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    for (account_id, direction) in [
+        (transfer.from_account_id, TransferDirection::Outgoing),
+        (transfer.to_account_id, TransferDirection::Incoming),
+    ] {
+        query(
+            r#"INSERT INTO transactions
+            (user_id, account_id, transfer_id, transfer_direction, description, amount, transaction_date, currency)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, (SELECT base_currency FROM users WHERE id = $1)))"#,
+        )
+        .bind(uuid)
+        .bind(account_id)
+        .bind(transfer_id)
+        .bind(direction)
+        .bind(&transfer.description)
+        .bind(transfer.amount)
+        .bind(transfer.transaction_date)
+        .bind(&currency)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let transfer = fetch_transfer(&mut tx, uuid, transfer_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(transfer))
+}
+
+pub async fn list_transfers(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Json<Vec<TransferInfo>>, GlobalAppError> {
+    Ok(Json(
+        query_as::<_, TransferInfo>(&format!(
+            "{TRANSFER_INFO_SELECT} WHERE tr.user_id = $1 ORDER BY o.transaction_date DESC, tr.id"
+        ))
+        .bind(uuid)
+        .fetch_all(&state.pool)
+        .await?,
+    ))
+}
+
+pub async fn display_transfer(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transfer_id): AppPath<Uuid>,
+) -> Result<Json<TransferInfo>, GlobalAppError> {
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(fetch_transfer(&mut conn, uuid, transfer_id).await?))
+}
+
+pub async fn update_transfer(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transfer_id): AppPath<Uuid>,
+    AppJson(patch): AppJson<PatchTransfer>,
+) -> Result<Json<TransferInfo>, GlobalAppError> {
+    if patch.from_account_id.is_none()
+        && patch.to_account_id.is_none()
+        && patch.amount.is_none()
+        && patch.currency.is_none()
+        && patch.transaction_date.is_none()
+        && patch.description.is_none()
+    {
+        return Err(GlobalAppError::validation(
+            "body",
+            "at least one field must be provided!",
+        ));
+    }
+    let mut tx = state.pool.begin().await?;
+
+    patch_transfer(&mut tx, uuid, transfer_id, &patch).await?;
+
+    let transfer = fetch_transfer(&mut tx, uuid, transfer_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(transfer))
+}
+
+pub async fn delete_transfer(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppPath(transfer_id): AppPath<Uuid>,
+) -> Result<String, GlobalAppError> {
+    // both legs go away through ON DELETE CASCADE
+    let result = query("DELETE FROM transfers WHERE id = $1 AND user_id = $2")
+        .bind(transfer_id)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(GlobalAppError::NotFound("transfer not found!".to_string()));
+    }
+
+    Ok("Transfer deleted successfully!".to_string())
+}
@@ -1,93 +1,310 @@
-use axum::{extract::State, http::StatusCode};
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
-use chrono::Utc;
-use sqlx::{query, query_as};
+use chrono::{DateTime, Utc};
+use sqlx::{Acquire, query, query_as};
 use uuid::Uuid;
 
 use crate::errors::GlobalAppError;
-use crate::helpers::users::{create_jwt, hash_password, verify_password};
+use crate::extractors::{AppJson, AppMultipart};
+use crate::helpers::currencies::parse_currency;
+use crate::helpers::login_attempts::{
+    begin_login_attempt, discard_login_attempt, invalid_credentials, recent_logins,
+    settle_login_attempt,
+};
+use crate::helpers::portability::{export_archive, import_archive};
+use crate::helpers::sessions::is_access_token_revoked;
+use crate::helpers::sessions::{
+    create_session, revoke_access_token, revoke_all_sessions, revoke_session, rotate_refresh_token,
+};
+use crate::helpers::two_factor::{
+    generate_recovery_codes, generate_totp_secret, match_totp_code, totp, verify_second_factor,
+};
+use crate::helpers::users::{
+    Claims, TokenScope, create_challenge_jwt, create_jwt, decode_jwt, hash_password,
+    normalize_identifier, validate_email, validate_username, verify_dummy_password,
+    verify_password,
+};
+use crate::helpers::verification::{
+    consume_user_token, send_password_reset_email, send_verification_email,
+};
 use crate::middlewares::GlobalAppState;
+use crate::models::currencies::BaseCurrency;
+use crate::models::login_attempts::{LoginContext, LoginOutcome};
+use crate::models::portability::ImportArchiveResponse;
+use crate::models::sessions::{RefreshTokenRequest, RefreshTokenResponse};
+use crate::models::two_factor::{
+    RecoveryCodesResponse, TwoFactorCodeRequest, TwoFactorDisableRequest, TwoFactorLoginRequest,
+    TwoFactorRow, TwoFactorSetupResponse,
+};
 use crate::models::users::{
-    HashPassword, LoginResponseUserDetails, LoginUserDetails, Password, PasswordPatch,
-    RegisterUserDetails, ResponseUserDetails, UserDetailRow, UserPasswordRow, UserProfileDetails,
+    EmailRequest, HashPassword, LoginResponseUserDetails, LoginUserDetails, Password,
+    PasswordPatch, PasswordResetRequest, RegisterUserDetails, ResponseUserDetails, TokenRequest,
+    UserDetailRow, UserPasswordRow, UserProfileDetails, UserTokenPurpose,
 };
 
 pub async fn register(
     State(state): State<GlobalAppState>,
-    Json(register_data): Json<RegisterUserDetails>,
+    AppJson(register_data): AppJson<RegisterUserDetails>,
 ) -> Result<Json<ResponseUserDetails>, GlobalAppError> {
-    let rows =
-        query_as::<_, UserDetailRow>("SELECT name, email FROM users WHERE name = $1 OR email = $2")
-            .bind(register_data.username.as_str())
-            .bind(register_data.email.as_str())
-            .fetch_all(&state.pool)
-            .await
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error".to_string(),
-                )
-            })?;
+    let base_currency = register_data
+        .base_currency
+        .as_deref()
+        .map(|code| parse_currency("base_currency", code))
+        .transpose()?
+        .unwrap_or_else(|| "USD".to_string());
+
+    let username = normalize_identifier(&register_data.username);
+    let email = normalize_identifier(&register_data.email);
+
+    let mut errors: Vec<_> = [validate_username(&username), validate_email(&email)]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+    errors.extend(
+        state
+            .password_policy
+            .violations("password", &register_data.password, &username, &email)
+            .await?,
+    );
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+
+    let rows = query_as::<_, UserDetailRow>(
+        "SELECT name, email FROM users WHERE lower(name) = $1 OR lower(email) = $2",
+    )
+    .bind(username.as_str())
+    .bind(email.as_str())
+    .fetch_all(&state.pool)
+    .await?;
 
     if !(rows.is_empty()) {
-        Err(GlobalAppError::new(
-            StatusCode::BAD_REQUEST,
+        Err(GlobalAppError::Conflict(
             "username or email already exists, try again !".to_string(),
         ))
     } else {
         let password_hash = hash_password(register_data.password).await?;
-        query("INSERT INTO users (name, email, password_hash, is_active) VALUES ($1, $2, $3, $4)")
-            .bind(register_data.username.as_str())
-            .bind(register_data.email.as_str())
+        let (user_id,) = query_as::<_, (Uuid,)>("INSERT INTO users (name, email, password_hash, is_active, base_currency) VALUES ($1, $2, $3, $4, $5) RETURNING id")
+            .bind(username.as_str())
+            .bind(email.as_str())
             .bind(password_hash)
             .bind(true)
-            .execute(&state.pool)
-            .await
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
-                )
-            })?;
+            .bind(base_currency)
+            .fetch_one(&state.pool)
+            .await?;
+
+        // the account exists either way, a lost email can be sent again
+        let to = email.clone();
+        tokio::spawn(async move {
+            let sent = match state.pool.acquire().await {
+                Ok(mut conn) => {
+                    send_verification_email(&mut conn, &state.mailer, user_id, &to).await
+                }
+                Err(error) => Err(error.into()),
+            };
+            if let Err(error) = sent {
+                eprintln!(
+                    "verification email to user {user_id} failed: {}",
+                    error.message()
+                );
+            }
+        });
 
         Ok(Json(ResponseUserDetails {
-            username: register_data.username,
-            email: register_data.email,
-            log_message: "User successfully registered".to_string(),
+            username,
+            email,
+            log_message:
+                "User successfully registered, check your inbox to verify your email address"
+                    .to_string(),
             token: None,
         }))
     }
 }
 
+/// takes a username or an email, throttled per account and per client address.
+/// every attempt ends up in `login_attempts` whatever its outcome, it is claimed before
+/// the password is checked so parallel attempts on one account count against each other
 pub async fn login(
     State(state): State<GlobalAppState>,
-    Json(login_data): Json<LoginUserDetails>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    AppJson(login_data): AppJson<LoginUserDetails>,
 ) -> Result<Json<LoginResponseUserDetails>, GlobalAppError> {
-    let row =
-        query_as::<_, UserPasswordRow>("SELECT id, name, password_hash FROM users WHERE name = $1")
-            .bind(login_data.username.as_str())
-            .fetch_one(&state.pool)
-            .await
-            .map_err(|error| match error {
-                sqlx::Error::RowNotFound => GlobalAppError::new(
-                    StatusCode::BAD_REQUEST,
-                    "user not found!, please register and try again".to_string(),
-                ),
-                _ => GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
-                ),
-            })?;
+    let context = LoginContext::from_request(&headers, peer, state.trust_forwarded_for);
+    let mut conn = state.pool.acquire().await?;
+
+    // usernames cannot contain '@', so the identifier says which column to look in
+    let identifier = normalize_identifier(&login_data.username);
+    let row = query_as::<_, UserPasswordRow>(if identifier.contains('@') {
+        "SELECT id, name, password_hash, email_verified_at, totp_enabled_at FROM users WHERE lower(email) = $1"
+    } else {
+        "SELECT id, name, password_hash, email_verified_at, totp_enabled_at FROM users WHERE lower(name) = $1"
+    })
+    .bind(identifier.as_str())
+    .fetch_optional(&mut *conn)
+    .await?;
+    let user_id = row.as_ref().map(|row| row.id);
+    // attempts by username and by email count against the same account
+    let username = row.as_ref().map_or(identifier, |row| row.name.clone());
+
+    let attempt_id = begin_login_attempt(&mut conn, user_id, &username, &context).await?;
+    // hashing takes a while, the connection goes back to the pool meanwhile
+    drop(conn);
+
+    let verified = match &row {
+        Some(row) => verify_password(login_data.password, row.password_hash.clone()).await,
+        None => {
+            verify_dummy_password(login_data.password).await;
+            Err(invalid_credentials())
+        }
+    };
+
+    let mut tx = state.pool.begin().await?;
+
+    let row = match (row, verified) {
+        (Some(row), Ok(())) => row,
+        (_, Err(error)) if !matches!(error, GlobalAppError::Unauthorized(_)) => return Err(error),
+        _ => {
+            settle_login_attempt(&mut tx, attempt_id, LoginOutcome::InvalidCredentials).await?;
+            tx.commit().await?;
+            return Err(invalid_credentials());
+        }
+    };
+
+    if state.require_verified_email && row.email_verified_at.is_none() {
+        settle_login_attempt(&mut tx, attempt_id, LoginOutcome::UnverifiedEmail).await?;
+        tx.commit().await?;
+        return Err(GlobalAppError::Forbidden(
+            "email address is not verified! check your inbox or request a new link".to_string(),
+        ));
+    }
+
+    if row.totp_enabled_at.is_some() {
+        settle_login_attempt(&mut tx, attempt_id, LoginOutcome::TwoFactorRequired).await?;
+        tx.commit().await?;
+        return Ok(Json(LoginResponseUserDetails {
+            username,
+            log_message: "two-factor code required!, send it with the challenge token to /users/login/2fa within 5 minutes".to_string(),
+            token: None,
+            refresh_token: None,
+            challenge_token: Some(create_challenge_jwt(row.id.to_string(), &state.hmac)?),
+        }));
+    }
+
+    settle_login_attempt(&mut tx, attempt_id, LoginOutcome::Success).await?;
+    let (session_id, refresh_token) = create_session(&mut tx, row.id).await?;
+
+    tx.commit().await?;
+
+    let jwt_token = create_jwt(row.id.to_string(), session_id.to_string(), state.hmac)?;
+
+    Ok(Json(LoginResponseUserDetails {
+        username,
+        log_message: "successfully logged in!, jwt token expires in 1 hour, use the refresh token to get a new one".to_string(),
+        token: Some(jwt_token),
+        refresh_token: Some(refresh_token),
+        challenge_token: None,
+    }))
+}
+
+/// second step of a login for users with two-factor enabled, the challenge token is
+/// spent along with the code. wrong codes count towards the same throttling as
+/// wrong passwords
+pub async fn login_two_factor(
+    State(state): State<GlobalAppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    AppJson(request): AppJson<TwoFactorLoginRequest>,
+) -> Result<Json<LoginResponseUserDetails>, GlobalAppError> {
+    let context = LoginContext::from_request(&headers, peer, state.trust_forwarded_for);
+    let invalid_challenge = || {
+        GlobalAppError::Unauthorized(
+            "challenge token is invalid or expired! please log in again".to_string(),
+        )
+    };
+
+    let claims = decode_jwt(&request.challenge_token, &state.hmac)?;
+    if claims.scope != TokenScope::TwoFactor {
+        return Err(invalid_challenge());
+    }
+    let user_id = Uuid::parse_str(&claims.sub).map_err(|_| invalid_challenge())?;
+    let jti = Uuid::parse_str(&claims.jti).map_err(|_| invalid_challenge())?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    if is_access_token_revoked(&mut conn, user_id, jti, claims.issued_at()).await? {
+        return Err(invalid_challenge());
+    }
+
+    let (username,) = query_as::<_, (String,)>("SELECT name FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(invalid_challenge)?;
+
+    let attempt_id = begin_login_attempt(&mut conn, Some(user_id), &username, &context).await?;
+
+    let mut tx = conn.begin().await?;
+
+    if !verify_second_factor(&mut tx, user_id, &request.code).await? {
+        settle_login_attempt(&mut tx, attempt_id, LoginOutcome::InvalidTwoFactor).await?;
+        // a failed check changes nothing else, so this only keeps the attempt on record
+        tx.commit().await?;
+        return Err(GlobalAppError::Unauthorized(
+            "invalid two-factor code!".to_string(),
+        ));
+    }
 
-    let hashed_password = row.password_hash;
-    verify_password(login_data.password, hashed_password).await?;
+    let expires_at = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+    revoke_access_token(&mut tx, user_id, jti, expires_at).await?;
 
-    let jwt_token = create_jwt(row.id.to_string(), state.hmac)?;
+    settle_login_attempt(&mut tx, attempt_id, LoginOutcome::Success).await?;
+    let (session_id, refresh_token) = create_session(&mut tx, user_id).await?;
+
+    tx.commit().await?;
+
+    let jwt_token = create_jwt(user_id.to_string(), session_id.to_string(), state.hmac)?;
 
     Ok(Json(LoginResponseUserDetails {
-        username: login_data.username,
-        log_message: "successfully logged in!, jwt token expires in 1 hour".to_string(),
+        username,
+        log_message: "successfully logged in!, jwt token expires in 1 hour, use the refresh token to get a new one".to_string(),
         token: Some(jwt_token),
+        refresh_token: Some(refresh_token),
+        challenge_token: None,
+    }))
+}
+
+pub async fn refresh_token(
+    State(state): State<GlobalAppState>,
+    AppJson(refresh_data): AppJson<RefreshTokenRequest>,
+) -> Result<Json<RefreshTokenResponse>, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let rotated = rotate_refresh_token(&mut tx, &refresh_data.refresh_token).await?;
+
+    // commit even on failure so that a detected token reuse stays revoked
+    tx.commit().await?;
+
+    let (session, new_refresh_token) = rotated.ok_or_else(|| {
+        GlobalAppError::Unauthorized(
+            "refresh token is invalid, expired or revoked! please log in again".to_string(),
+        )
+    })?;
+
+    let jwt_token = create_jwt(
+        session.user_id.to_string(),
+        session.id.to_string(),
+        state.hmac,
+    )?;
+
+    Ok(Json(RefreshTokenResponse {
+        log_message: "token refreshed!, jwt token expires in 1 hour".to_string(),
+        token: jwt_token,
+        refresh_token: new_refresh_token,
     }))
 }
 
@@ -95,62 +312,86 @@ pub async fn my_profile(
     State(state): State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
 ) -> Result<Json<UserProfileDetails>, GlobalAppError> {
-    Ok(Json(
-        query_as::<_, UserProfileDetails>(
-            "SELECT id, name, email, created_at, updated_at, is_active FROM users WHERE id = $1",
-        )
+    let mut conn = state.pool.acquire().await?;
+
+    let mut profile = query_as::<_, UserProfileDetails>(
+        "SELECT id, name, email, created_at, updated_at, is_active, email_verified_at, totp_enabled_at IS NOT NULL AS two_factor_enabled, base_currency FROM users WHERE id = $1",
+    )
+    .bind(uuid)
+    .fetch_one(&mut *conn)
+    .await?;
+    profile.recent_logins = recent_logins(&mut conn, uuid).await?;
+
+    Ok(Json(profile))
+}
+
+/// amounts are converted on read, so switching currencies never rewrites history
+pub async fn update_base_currency(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(currency): AppJson<BaseCurrency>,
+) -> Result<Json<BaseCurrency>, GlobalAppError> {
+    let base_currency = parse_currency("base_currency", &currency.base_currency)?;
+
+    query("UPDATE users SET base_currency = $1, updated_at = $2 WHERE id = $3")
+        .bind(&base_currency)
+        .bind(Utc::now())
         .bind(uuid)
-        .fetch_one(&state.pool)
-        .await
-        .map_err(|_| {
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            )
-        })?,
-    ))
+        .execute(&state.pool)
+        .await?;
+
+    Ok(Json(BaseCurrency { base_currency }))
 }
 
 pub async fn update_password(
     State(state): State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-    Json(patch_password): Json<PasswordPatch>,
+    AppJson(patch_password): AppJson<PasswordPatch>,
 ) -> Result<String, GlobalAppError> {
-    let row =
-        query_as::<_, UserPasswordRow>("SELECT id, name, password_hash FROM users WHERE id = $1")
-            .bind(uuid)
-            .fetch_one(&state.pool)
-            .await
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
-                )
-            })?;
+    let row = query_as::<_, UserPasswordRow>(
+        "SELECT id, name, email, password_hash FROM users WHERE id = $1",
+    )
+    .bind(uuid)
+    .fetch_one(&state.pool)
+    .await?;
 
     verify_password(patch_password.old_password, row.password_hash).await?;
 
+    let errors = state
+        .password_policy
+        .violations(
+            "new_password",
+            &patch_password.new_password,
+            &row.name,
+            &row.email,
+        )
+        .await?;
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+
     let new_password_hash = hash_password(patch_password.new_password).await?;
+
+    let mut tx = state.pool.begin().await?;
+
     query("UPDATE users SET password_hash = $1, updated_at = $2 WHERE id = $3")
         .bind(new_password_hash)
         .bind(Utc::now())
         .bind(uuid)
-        .execute(&state.pool)
-        .await
-        .map_err(|_| {
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            )
-        })?;
+        .execute(&mut *tx)
+        .await?;
+
+    revoke_all_sessions(&mut tx, uuid).await?;
 
-    Ok("Password updated successfully!".to_string())
+    tx.commit().await?;
+
+    Ok("Password updated successfully!, please log in again".to_string())
 }
 
 pub async fn delete_user(
     State(state): State<GlobalAppState>,
     Extension(uuid): Extension<Uuid>,
-    Json(user_password): Json<Password>,
+    AppJson(user_password): AppJson<Password>,
 ) -> Result<String, GlobalAppError> {
     let password_hash =
         query_as::<_, HashPassword>("SELECT password_hash FROM users WHERE id = $1")
@@ -158,14 +399,10 @@ pub async fn delete_user(
             .fetch_one(&state.pool)
             .await
             .map_err(|error| match error {
-                sqlx::Error::RowNotFound => GlobalAppError::new(
-                    StatusCode::BAD_REQUEST,
-                    "User deleted, register again!".to_string(),
-                ),
-                _ => GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "database error!".to_string(),
-                ),
+                sqlx::Error::RowNotFound => {
+                    GlobalAppError::NotFound("User deleted, register again!".to_string())
+                }
+                _ => error.into(),
             })?
             .password_hash;
 
@@ -174,13 +411,371 @@ pub async fn delete_user(
     query("DELETE FROM users WHERE id = $1")
         .bind(uuid)
         .execute(&state.pool)
-        .await
-        .map_err(|_| {
-            GlobalAppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "database error!".to_string(),
-            )
-        })?;
+        .await?;
 
     Ok("User deleted successfully!".to_string())
 }
+
+pub async fn logout(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    Extension(claims): Extension<Claims>,
+) -> Result<String, GlobalAppError> {
+    let jti = Uuid::parse_str(&claims.jti)
+        .map_err(|_| GlobalAppError::BadRequest("invalid token id!".to_string()))?;
+    let session_id = Uuid::parse_str(&claims.sid)
+        .map_err(|_| GlobalAppError::BadRequest("invalid session id!".to_string()))?;
+    let expires_at = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+
+    let mut tx = state.pool.begin().await?;
+
+    revoke_access_token(&mut tx, uuid, jti, expires_at).await?;
+    revoke_session(&mut tx, uuid, session_id).await?;
+
+    tx.commit().await?;
+
+    Ok("Logged out successfully!".to_string())
+}
+
+pub async fn logout_all(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<String, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    revoke_all_sessions(&mut tx, uuid).await?;
+
+    tx.commit().await?;
+
+    Ok("Logged out of all sessions successfully!".to_string())
+}
+
+/// zip archive with everything stored about the user, see `export_archive`
+pub async fn export_user_data(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Response, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+    // one snapshot, so rows written while the archive is built cannot leave it inconsistent
+    query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let archive = export_archive(&mut tx, uuid).await?;
+
+    tx.commit().await?;
+
+    let disposition = format!(
+        r#"attachment; filename="expense-tracker-export-{}.zip""#,
+        Utc::now().format("%Y%m%d")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    )
+        .into_response())
+}
+
+/// multipart form with an archive from `export_user_data` in `file`, restored into the
+/// current account with new ids
+pub async fn import_user_data(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppMultipart(mut multipart): AppMultipart,
+) -> Result<Json<ImportArchiveResponse>, GlobalAppError> {
+    let mut file = None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file") {
+            file = Some(field.bytes().await?);
+        }
+    }
+    let file = file.ok_or_else(|| GlobalAppError::validation("file", "file is required!"))?;
+
+    let mut tx = state.pool.begin().await?;
+
+    let imported = import_archive(&mut tx, uuid, &file).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(ImportArchiveResponse {
+        message: "Data imported successfully!".to_string(),
+        imported,
+    }))
+}
+
+pub async fn verify_email(
+    State(state): State<GlobalAppState>,
+    AppJson(request): AppJson<TokenRequest>,
+) -> Result<String, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let user_id = consume_user_token(&mut tx, &request.token, UserTokenPurpose::VerifyEmail)
+        .await?
+        .ok_or_else(|| GlobalAppError::validation("token", "invalid or expired token!"))?;
+
+    query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok("Email verified successfully!".to_string())
+}
+
+/// answers the same whether or not the address belongs to an unverified account
+pub async fn resend_verification_email(
+    State(state): State<GlobalAppState>,
+    AppJson(request): AppJson<EmailRequest>,
+) -> Result<String, GlobalAppError> {
+    let user = query_as::<_, (Uuid, String)>(
+        "SELECT id, email FROM users WHERE lower(email) = $1 AND email_verified_at IS NULL",
+    )
+    .bind(normalize_identifier(&request.email))
+    .fetch_optional(&state.pool)
+    .await?;
+
+    // sent in the background so the answer takes as long for unknown addresses
+    if let Some((user_id, email)) = user {
+        tokio::spawn(async move {
+            let sent = match state.pool.acquire().await {
+                Ok(mut conn) => {
+                    send_verification_email(&mut conn, &state.mailer, user_id, &email).await
+                }
+                Err(error) => Err(error.into()),
+            };
+            if let Err(error) = sent {
+                eprintln!(
+                    "verification email to user {user_id} failed: {}",
+                    error.message()
+                );
+            }
+        });
+    }
+
+    Ok("If the address belongs to an unverified account, a new link is on its way!".to_string())
+}
+
+/// answers the same whether or not the address belongs to an account
+pub async fn forgot_password(
+    State(state): State<GlobalAppState>,
+    AppJson(request): AppJson<EmailRequest>,
+) -> Result<String, GlobalAppError> {
+    let user = query_as::<_, (Uuid, String)>("SELECT id, email FROM users WHERE lower(email) = $1")
+        .bind(normalize_identifier(&request.email))
+        .fetch_optional(&state.pool)
+        .await?;
+
+    // sent in the background so the answer takes as long for unknown addresses
+    if let Some((user_id, email)) = user {
+        tokio::spawn(async move {
+            let sent = match state.pool.acquire().await {
+                Ok(mut conn) => {
+                    send_password_reset_email(&mut conn, &state.mailer, user_id, &email).await
+                }
+                Err(error) => Err(error.into()),
+            };
+            if let Err(error) = sent {
+                eprintln!(
+                    "password reset email to user {user_id} failed: {}",
+                    error.message()
+                );
+            }
+        });
+    }
+
+    Ok("If the address belongs to an account, a reset link is on its way!".to_string())
+}
+
+/// sets a new password with a token from `forgot_password` and logs out every session,
+/// the email address counts as verified since the token was delivered to it. a password
+/// refused by the policy leaves the token unspent
+pub async fn reset_password(
+    State(state): State<GlobalAppState>,
+    AppJson(request): AppJson<PasswordResetRequest>,
+) -> Result<String, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let user_id = consume_user_token(&mut tx, &request.token, UserTokenPurpose::ResetPassword)
+        .await?
+        .ok_or_else(|| GlobalAppError::validation("token", "invalid or expired token!"))?;
+
+    let (name, email) =
+        query_as::<_, (String, String)>("SELECT name, email FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    let errors = state
+        .password_policy
+        .violations("new_password", &request.new_password, &name, &email)
+        .await?;
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+
+    let new_password_hash = hash_password(request.new_password).await?;
+
+    query(
+        r#"UPDATE users SET
+        password_hash = $1,
+        updated_at = $2,
+        email_verified_at = COALESCE(email_verified_at, $2)
+        WHERE id = $3"#,
+    )
+    .bind(new_password_hash)
+    .bind(Utc::now())
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    revoke_all_sessions(&mut tx, user_id).await?;
+
+    tx.commit().await?;
+
+    Ok("Password reset successfully!, please log in again".to_string())
+}
+
+/// starts enrollment with a fresh secret, nothing changes for logins until it is confirmed
+pub async fn setup_two_factor(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+) -> Result<Json<TwoFactorSetupResponse>, GlobalAppError> {
+    let (name, totp_enabled_at) = query_as::<_, (String, Option<DateTime<Utc>>)>(
+        "SELECT name, totp_enabled_at FROM users WHERE id = $1",
+    )
+    .bind(uuid)
+    .fetch_one(&state.pool)
+    .await?;
+
+    if totp_enabled_at.is_some() {
+        return Err(GlobalAppError::Conflict(
+            "two-factor authentication is already enabled!".to_string(),
+        ));
+    }
+
+    let secret = generate_totp_secret();
+
+    query("UPDATE users SET totp_secret = $1, totp_last_step = NULL WHERE id = $2")
+        .bind(&secret)
+        .bind(uuid)
+        .execute(&state.pool)
+        .await?;
+
+    Ok(Json(TwoFactorSetupResponse {
+        log_message: "add the secret to your authenticator app, then confirm with a code from it"
+            .to_string(),
+        otpauth_uri: totp(&secret, &name)?.get_url(),
+        secret,
+    }))
+}
+
+/// turns two-factor on once the authenticator app proves it has the secret
+pub async fn confirm_two_factor(
+    State(state): State<GlobalAppState>,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(request): AppJson<TwoFactorCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, GlobalAppError> {
+    let mut tx = state.pool.begin().await?;
+
+    let row = query_as::<_, TwoFactorRow>(
+        "SELECT totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1 FOR UPDATE",
+    )
+    .bind(uuid)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if row.totp_enabled_at.is_some() {
+        return Err(GlobalAppError::Conflict(
+            "two-factor authentication is already enabled!".to_string(),
+        ));
+    }
+    let secret = row.totp_secret.ok_or_else(|| {
+        GlobalAppError::BadRequest(
+            "two-factor setup not started! call /users/me/2fa/setup first".to_string(),
+        )
+    })?;
+
+    let step = match_totp_code(&secret, request.code.trim(), row.totp_last_step, Utc::now())?
+        .ok_or_else(|| GlobalAppError::validation("code", "invalid two-factor code!"))?;
+
+    query("UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $1 WHERE id = $2")
+        .bind(step)
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await?;
+
+    let recovery_codes = generate_recovery_codes(&mut tx, uuid).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(RecoveryCodesResponse {
+        log_message: "two-factor authentication enabled!, store the recovery codes somewhere safe, each one works once".to_string(),
+        recovery_codes,
+    }))
+}
+
+/// wrong passwords and codes count towards the login throttling, a stolen access token
+/// must not be a way around it
+pub async fn disable_two_factor(
+    State(state): State<GlobalAppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(uuid): Extension<Uuid>,
+    AppJson(request): AppJson<TwoFactorDisableRequest>,
+) -> Result<String, GlobalAppError> {
+    let context = LoginContext::from_request(&headers, peer, state.trust_forwarded_for);
+    let mut conn = state.pool.acquire().await?;
+
+    let (username, password_hash) =
+        query_as::<_, (String, String)>("SELECT name, password_hash FROM users WHERE id = $1")
+            .bind(uuid)
+            .fetch_one(&mut *conn)
+            .await?;
+
+    let attempt_id = begin_login_attempt(&mut conn, Some(uuid), &username, &context).await?;
+    drop(conn);
+
+    let verified = verify_password(request.password, password_hash).await;
+
+    let mut tx = state.pool.begin().await?;
+
+    match verified {
+        Ok(()) => {}
+        Err(GlobalAppError::Unauthorized(message)) => {
+            settle_login_attempt(&mut tx, attempt_id, LoginOutcome::InvalidCredentials).await?;
+            tx.commit().await?;
+            return Err(GlobalAppError::Unauthorized(message));
+        }
+        Err(error) => return Err(error),
+    }
+
+    if !verify_second_factor(&mut tx, uuid, &request.code).await? {
+        settle_login_attempt(&mut tx, attempt_id, LoginOutcome::InvalidTwoFactor).await?;
+        tx.commit().await?;
+        return Err(GlobalAppError::validation(
+            "code",
+            "invalid two-factor code!",
+        ));
+    }
+
+    discard_login_attempt(&mut tx, attempt_id).await?;
+
+    query("UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = $1")
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await?;
+
+    query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok("Two-factor authentication disabled successfully!".to_string())
+}
@@ -0,0 +1,20 @@
+use sqlx::{PgConnection, query_as};
+use uuid::Uuid;
+
+use crate::errors::GlobalAppError;
+
+/// makes sure the account exists and belongs to the user before it gets referenced
+pub async fn ensure_account_owned(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_id: Uuid,
+    field: &str,
+) -> Result<(), GlobalAppError> {
+    query_as::<_, (Uuid,)>("SELECT id FROM accounts WHERE id = $1 AND user_id = $2")
+        .bind(account_id)
+        .bind(user_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| GlobalAppError::validation(field, "account not found!"))
+}
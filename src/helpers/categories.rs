@@ -0,0 +1,29 @@
+use slug::slugify;
+use sqlx::{PgConnection, query_as};
+use uuid::Uuid;
+
+use crate::{errors::GlobalAppError, models::categories::GetUserCategories};
+
+/// looks a category up by its name or slug
+pub async fn category_by_slug(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    category: &str,
+) -> Result<GetUserCategories, GlobalAppError> {
+    query_as::<_, GetUserCategories>(
+        "SELECT id, name, created_at, type, is_savings FROM categories WHERE slug = $1 AND user_id = $2",
+    )
+    .bind(slugify(category))
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::validation("category", "category not found!"))
+}
+
+pub async fn category_id_by_slug(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    category: &str,
+) -> Result<Uuid, GlobalAppError> {
+    Ok(category_by_slug(conn, user_id, category).await?.id)
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use iso_currency::Currency;
+use sqlx::{PgConnection, query_as};
+use uuid::Uuid;
+
+use crate::{errors::GlobalAppError, models::currencies::MissingExchangeRate};
+
+/// joins the owner and the rate converting `t.amount` into their base currency as
+/// `fx.rate`, expects transactions to be aliased as `t`. a macro so it can be `concat!`ed
+/// into other query constants
+macro_rules! exchange_rate_join {
+    () => {
+        r#"
+        INNER JOIN users u
+        ON u.id = t.user_id
+        CROSS JOIN LATERAL (
+            SELECT exchange_rate(
+                t.user_id,
+                t.currency,
+                u.base_currency,
+                (t.transaction_date AT TIME ZONE 'UTC')::date
+            ) AS rate
+        ) fx"#
+    };
+}
+pub(crate) use exchange_rate_join;
+
+pub const EXCHANGE_RATE_JOIN: &str = exchange_rate_join!();
+
+/// transactions that count towards income and expense totals, transfer legs have no
+/// category and never do
+pub const CATEGORIZED_TRANSACTIONS: &str = "t.category_id IS NOT NULL";
+/// transactions that count towards account balances, transfer legs included
+pub const ACCOUNT_TRANSACTIONS: &str = "t.account_id IS NOT NULL";
+/// transactions that count towards the spending of the user's budgets
+pub const BUDGETED_TRANSACTIONS: &str =
+    "t.category_id IN (SELECT category_id FROM budgets WHERE user_id = $1)";
+
+/// uppercased ISO 4217 code, None when the code is unknown
+pub fn normalize_currency(code: &str) -> Option<String> {
+    Currency::from_code(&code.trim().to_uppercase()).map(|currency| currency.code().to_string())
+}
+
+/// like `normalize_currency`, reporting `field` back when the code is unknown
+pub fn parse_currency(field: &str, code: &str) -> Result<String, GlobalAppError> {
+    normalize_currency(code).ok_or_else(|| {
+        GlobalAppError::validation(
+            field,
+            &format!("'{}' is not an ISO 4217 currency!", code.trim()),
+        )
+    })
+}
+
+pub async fn base_currency(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<String, GlobalAppError> {
+    Ok(
+        query_as::<_, (String,)>("SELECT base_currency FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await?
+            .0,
+    )
+}
+
+/// fails when a transaction in `scope` and the range cannot be converted into the base
+/// currency, so totals never silently leave out foreign amounts. `scope` is one of the
+/// conditions above and picks the transactions the total is built from
+pub async fn ensure_exchange_rates(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    scope: &str,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<(), GlobalAppError> {
+    let missing = query_as::<_, MissingExchangeRate>(&format!(
+        r#"SELECT
+        t.currency AS from_currency,
+        u.base_currency AS to_currency,
+        MIN((t.transaction_date AT TIME ZONE 'UTC')::date) AS first_needed_on
+        FROM transactions t
+        {EXCHANGE_RATE_JOIN}
+        WHERE t.user_id = $1
+        AND {scope}
+        AND ($2::timestamptz IS NULL OR t.transaction_date >= $2)
+        AND ($3::timestamptz IS NULL OR t.transaction_date <= $3)
+        AND fx.rate IS NULL
+        GROUP BY t.currency, u.base_currency
+        ORDER BY t.currency"#
+    ))
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let pairs = missing
+        .iter()
+        .map(|rate| {
+            format!(
+                "{} to {} effective on or before {}",
+                rate.from_currency, rate.to_currency, rate.first_needed_on
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Err(GlobalAppError::Unprocessable(format!(
+        "missing exchange rates: {pairs}, upload them through /exchange-rates!"
+    )))
+}
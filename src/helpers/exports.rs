@@ -0,0 +1,467 @@
+use std::{
+    io::{self, Write},
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::{TryStreamExt, stream};
+use rust_decimal::Decimal;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tokio::{
+    sync::{Semaphore, SemaphorePermit, mpsc},
+    time::timeout,
+};
+use zip::{
+    CompressionMethod, ZipWriter,
+    write::{SimpleFileOptions, StreamWriter},
+};
+
+use crate::{
+    errors::GlobalAppError,
+    models::{
+        categories::CategoryType,
+        exports::{ExportFormat, TransactionExportRow},
+        transactions::TransactionInfo,
+        transfers::TransferDirection,
+    },
+};
+
+pub const EXPORT_COLUMNS: [&str; 14] = [
+    "id",
+    "transaction_date",
+    "description",
+    "amount",
+    "currency",
+    "base_amount",
+    "base_currency",
+    "exchange_rate",
+    "category",
+    "category_type",
+    "is_savings",
+    "account_id",
+    "transfer_id",
+    "transfer_direction",
+];
+
+/// bytes collected before they are handed to the response body
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// a sheet holds this many rows, the header included
+pub const XLSX_MAX_ROWS: i64 = 1_048_576;
+
+/// every running export keeps a database connection busy until its client has read it all
+static EXPORT_SLOTS: Semaphore = Semaphore::const_new(4);
+
+/// a client that reads nothing for this long loses its export and the connection it holds
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn content_type(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "text/csv; charset=utf-8",
+        ExportFormat::Jsonl => "application/jsonl",
+        ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    }
+}
+
+pub fn file_extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Jsonl => "jsonl",
+        ExportFormat::Xlsx => "xlsx",
+    }
+}
+
+/// in-memory sink the encoders write into while the exporter drains it chunk by chunk
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn len(&self) -> usize {
+        self.0.lock().map_or(0, |buffer| buffer.len())
+    }
+
+    fn take(&self) -> Bytes {
+        self.0
+            .lock()
+            .map(|mut buffer| Bytes::from(mem::take(&mut *buffer)))
+            .unwrap_or_default()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("export buffer poisoned"))?
+            .extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const XLSX_CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const XLSX_ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const XLSX_WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Transactions" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+
+const XLSX_WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+/// style 1 formats dates, style 2 makes the header bold
+const XLSX_STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="1"><numFmt numFmtId="164" formatCode="yyyy-mm-dd hh:mm:ss"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/></cellXfs></styleSheet>"#;
+
+const XLSX_SHEET_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#;
+
+const XLSX_SHEET_END: &str = "</sheetData></worksheet>";
+
+enum XlsxCell<'a> {
+    Text(&'a str),
+    Number(Decimal),
+    Date(DateTime<Utc>),
+    Bool(bool),
+    Empty,
+}
+
+/// spreadsheets run text starting with these as a formula, a leading quote keeps it text
+fn escape_formula(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .chars()
+        // control characters other than tabs and newlines are not allowed in xml at all
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .fold(String::with_capacity(value.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                _ => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+/// spreadsheets count days since 1899-12-30 with the time of day as the fraction
+fn excel_serial(date: DateTime<Utc>) -> f64 {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)
+        .unwrap_or_default()
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc();
+    (date - epoch).num_milliseconds() as f64 / 86_400_000.0
+}
+
+fn xlsx_row(number: usize, cells: &[XlsxCell<'_>], style: Option<u8>) -> String {
+    let mut row = format!(r#"<row r="{number}">"#);
+    for (index, cell) in cells.iter().enumerate() {
+        // there are fewer than 26 columns so a single letter is enough
+        let reference = format!("{}{number}", char::from(b'A' + index as u8));
+        let style = style
+            .map(|style| format!(r#" s="{style}""#))
+            .unwrap_or_default();
+        match cell {
+            XlsxCell::Text(text) => row.push_str(&format!(
+                r#"<c r="{reference}" t="inlineStr"{style}><is><t xml:space="preserve">{}</t></is></c>"#,
+                escape_xml(text)
+            )),
+            XlsxCell::Number(number) => {
+                row.push_str(&format!(r#"<c r="{reference}"{style}><v>{number}</v></c>"#))
+            }
+            XlsxCell::Date(date) => row.push_str(&format!(
+                r#"<c r="{reference}" s="1"><v>{}</v></c>"#,
+                excel_serial(*date)
+            )),
+            XlsxCell::Bool(value) => row.push_str(&format!(
+                r#"<c r="{reference}" t="b"{style}><v>{}</v></c>"#,
+                u8::from(*value)
+            )),
+            XlsxCell::Empty => {}
+        }
+    }
+    row.push_str("</row>");
+    row
+}
+
+/// a single sheet workbook written on the fly, the sheet is the last file in the archive
+/// so rows can be added until the export is done
+fn start_xlsx(
+    buffer: SharedBuffer,
+) -> zip::result::ZipResult<ZipWriter<StreamWriter<SharedBuffer>>> {
+    let mut zip = ZipWriter::new_stream(buffer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, contents) in [
+        ("[Content_Types].xml", XLSX_CONTENT_TYPES),
+        ("_rels/.rels", XLSX_ROOT_RELS),
+        ("xl/workbook.xml", XLSX_WORKBOOK),
+        ("xl/_rels/workbook.xml.rels", XLSX_WORKBOOK_RELS),
+        ("xl/styles.xml", XLSX_STYLES),
+    ] {
+        zip.start_file(name, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+
+    zip.start_file("xl/worksheets/sheet1.xml", options)?;
+    zip.write_all(XLSX_SHEET_START.as_bytes())?;
+    let header = EXPORT_COLUMNS.map(XlsxCell::Text);
+    zip.write_all(xlsx_row(1, &header, Some(2)).as_bytes())?;
+
+    Ok(zip)
+}
+
+enum ExportWriter {
+    Csv(Box<csv::Writer<SharedBuffer>>),
+    Jsonl(SharedBuffer),
+    Xlsx {
+        zip: Box<ZipWriter<StreamWriter<SharedBuffer>>>,
+        rows: usize,
+    },
+}
+
+impl ExportWriter {
+    fn start(format: ExportFormat, buffer: SharedBuffer) -> io::Result<Self> {
+        Ok(match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(buffer);
+                writer.write_record(EXPORT_COLUMNS)?;
+                Self::Csv(Box::new(writer))
+            }
+            ExportFormat::Jsonl => Self::Jsonl(buffer),
+            ExportFormat::Xlsx => Self::Xlsx {
+                zip: Box::new(start_xlsx(buffer).map_err(io::Error::other)?),
+                rows: 1,
+            },
+        })
+    }
+
+    fn write(&mut self, mut row: TransactionExportRow) -> io::Result<()> {
+        // users type these two themselves, everything else is generated or validated
+        if !matches!(self, Self::Jsonl(_)) {
+            row.description = row.description.map(escape_formula);
+            row.category = row.category.map(escape_formula);
+        }
+
+        match self {
+            Self::Csv(writer) => {
+                writer.serialize(&row)?;
+                writer.flush()
+            }
+            Self::Jsonl(buffer) => {
+                serde_json::to_writer(&mut *buffer, &row)?;
+                buffer.write_all(b"\n")
+            }
+            Self::Xlsx { zip, rows } => {
+                *rows += 1;
+                if *rows as i64 > XLSX_MAX_ROWS {
+                    return Err(io::Error::other("too many rows for a spreadsheet"));
+                }
+                let id = row.id.to_string();
+                let account_id = row.account_id.map(|id| id.to_string());
+                let transfer_id = row.transfer_id.map(|id| id.to_string());
+                let category_type = row.category_type.map(|category_type| match category_type {
+                    CategoryType::Expense => "expense",
+                    CategoryType::Income => "income",
+                });
+                let transfer_direction = row.transfer_direction.map(|direction| match direction {
+                    TransferDirection::Outgoing => "outgoing",
+                    TransferDirection::Incoming => "incoming",
+                });
+                let cells = [
+                    XlsxCell::Text(&id),
+                    XlsxCell::Date(row.transaction_date),
+                    row.description
+                        .as_deref()
+                        .map_or(XlsxCell::Empty, XlsxCell::Text),
+                    XlsxCell::Number(row.amount),
+                    XlsxCell::Text(&row.currency),
+                    row.base_amount.map_or(XlsxCell::Empty, XlsxCell::Number),
+                    XlsxCell::Text(&row.base_currency),
+                    row.exchange_rate.map_or(XlsxCell::Empty, XlsxCell::Number),
+                    row.category
+                        .as_deref()
+                        .map_or(XlsxCell::Empty, XlsxCell::Text),
+                    category_type.map_or(XlsxCell::Empty, XlsxCell::Text),
+                    row.is_savings.map_or(XlsxCell::Empty, XlsxCell::Bool),
+                    account_id
+                        .as_deref()
+                        .map_or(XlsxCell::Empty, XlsxCell::Text),
+                    transfer_id
+                        .as_deref()
+                        .map_or(XlsxCell::Empty, XlsxCell::Text),
+                    transfer_direction.map_or(XlsxCell::Empty, XlsxCell::Text),
+                ];
+                zip.write_all(xlsx_row(*rows, &cells, None).as_bytes())
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Csv(mut writer) => writer.flush(),
+            Self::Jsonl(_) => Ok(()),
+            Self::Xlsx { mut zip, .. } => {
+                zip.write_all(XLSX_SHEET_END.as_bytes())?;
+                zip.finish().map(|_| ()).map_err(io::Error::other)
+            }
+        }
+    }
+}
+
+async fn write_export(
+    pool: PgPool,
+    mut builder: QueryBuilder<'static, Postgres>,
+    format: ExportFormat,
+    sender: &mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<()> {
+    let buffer = SharedBuffer::default();
+    let mut writer = ExportWriter::start(format, buffer.clone())?;
+
+    let mut transactions = builder.build_query_as::<TransactionInfo>().fetch(&pool);
+    while let Some(transaction) = transactions.try_next().await.map_err(io::Error::other)? {
+        writer.write(TransactionExportRow::from(transaction))?;
+
+        if buffer.len() >= CHUNK_SIZE && !send_chunk(sender, buffer.take()).await {
+            return Ok(());
+        }
+    }
+    writer.finish()?;
+
+    send_chunk(sender, buffer.take()).await;
+
+    Ok(())
+}
+
+/// false once the client went away or stopped reading, nobody is left for the rest
+async fn send_chunk(sender: &mpsc::Sender<io::Result<Bytes>>, chunk: Bytes) -> bool {
+    matches!(
+        timeout(SEND_TIMEOUT, sender.send(Ok(chunk))).await,
+        Ok(Ok(()))
+    )
+}
+
+/// streams the rows `builder` selects into a response body as they come out of postgres,
+/// a failure halfway through aborts the body so the client sees a broken download
+/// instead of a silently truncated file
+pub fn export_body(
+    pool: PgPool,
+    builder: QueryBuilder<'static, Postgres>,
+    format: ExportFormat,
+    permit: SemaphorePermit<'static>,
+) -> Body {
+    let (sender, receiver) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        if let Err(error) = write_export(pool, builder, format, &sender).await {
+            eprintln!("export failed: {error}");
+            let _ = sender.send(Err(error)).await;
+        }
+        drop(permit);
+    });
+
+    Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }))
+}
+
+/// a slot for one more export, taken before anything is queried
+pub fn reserve_export() -> Result<SemaphorePermit<'static>, GlobalAppError> {
+    EXPORT_SLOTS
+        .try_acquire()
+        .map_err(|_| GlobalAppError::RateLimited {
+            message: "too many exports are running, try again shortly!".to_string(),
+            retry_after_secs: 10,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn formulas_are_kept_as_text() {
+        for value in ["=1+1", "+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(escape_formula(value.to_string()), format!("'{value}"));
+        }
+        assert_eq!(escape_formula("rent".to_string()), "rent");
+        assert_eq!(escape_formula(String::new()), "");
+    }
+
+    #[test]
+    fn xml_is_escaped_and_control_characters_dropped() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("a\u{0}b\u{1b}c"), "abc");
+        assert_eq!(escape_xml("a\tb\nc\rd"), "a\tb\nc\rd");
+    }
+
+    #[test]
+    fn excel_serials_count_days_from_1899_12_30() {
+        let date = |y, m, d, h| Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+
+        assert_eq!(excel_serial(date(1899, 12, 30, 0)), 0.0);
+        assert_eq!(excel_serial(date(1900, 3, 1, 0)), 61.0);
+        assert_eq!(excel_serial(date(2024, 1, 1, 12)), 45292.5);
+    }
+
+    #[test]
+    fn xlsx_rows_reference_their_cells() {
+        let date = Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap();
+        let row = xlsx_row(
+            3,
+            &[
+                XlsxCell::Text("a & b"),
+                XlsxCell::Number(dec!(-1.50)),
+                XlsxCell::Empty,
+                XlsxCell::Date(date),
+                XlsxCell::Bool(true),
+            ],
+            None,
+        );
+
+        assert_eq!(
+            row,
+            concat!(
+                r#"<row r="3">"#,
+                r#"<c r="A3" t="inlineStr"><is><t xml:space="preserve">a &amp; b</t></is></c>"#,
+                r#"<c r="B3"><v>-1.50</v></c>"#,
+                r#"<c r="D3" s="1"><v>2</v></c>"#,
+                r#"<c r="E3" t="b"><v>1</v></c>"#,
+                "</row>"
+            )
+        );
+    }
+
+    #[test]
+    fn xlsx_header_rows_carry_their_style() {
+        assert_eq!(
+            xlsx_row(1, &[XlsxCell::Text("id")], Some(2)),
+            r#"<row r="1"><c r="A1" t="inlineStr" s="2"><is><t xml:space="preserve">id</t></is></c></row>"#
+        );
+    }
+}
@@ -0,0 +1,1256 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use csv::{ReaderBuilder, StringRecord};
+use rust_decimal::Decimal;
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, PgConnection, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    helpers::{
+        categories::category_by_slug,
+        currencies::normalize_currency,
+        transactions::{insert_transaction, possible_duplicates},
+    },
+    models::{
+        categories::CategoryType,
+        imports::{
+            ColumnRef, CsvMapping, ImportResponse, ImportRowResult, ImportRowStatus, ParsedRow,
+            StatementOptions,
+        },
+        transactions::TransactionRequest,
+    },
+};
+
+pub const MAX_IMPORT_ROWS: usize = 5000;
+
+/// accepts RFC 3339 and plain %Y-%m-%d unless the file uses its own format,
+/// dates without a time are taken as midnight UTC
+pub fn parse_date(value: &str, format: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    let parsed = match format {
+        Some(format) => NaiveDateTime::parse_from_str(value, format)
+            .map(|date| date.and_utc())
+            .or_else(|_| {
+                NaiveDate::parse_from_str(value, format)
+                    .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+            })
+            .ok(),
+        None => DateTime::parse_from_rfc3339(value)
+            .map(|date| date.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+            })
+            .ok(),
+    };
+
+    parsed.ok_or_else(|| format!("'{value}' is not a valid date!"))
+}
+
+/// signed amount as banks write it, currency symbols, spaces and thousands separators
+/// are ignored and (12.50) counts as negative
+pub fn parse_amount(value: &str, decimal_comma: bool) -> Result<Decimal, String> {
+    let trimmed = value.trim();
+    let negative = trimmed.starts_with('(') && trimmed.ends_with(')');
+    let (thousands, decimal) = if decimal_comma {
+        ('.', ',')
+    } else {
+        (',', '.')
+    };
+
+    let normalized: String = trimmed
+        .chars()
+        .filter(|c| *c != thousands)
+        .map(|c| if c == decimal { '.' } else { c })
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+'))
+        .collect();
+
+    let amount = normalized
+        .parse::<Decimal>()
+        .map_err(|_| format!("'{trimmed}' is not a valid amount!"))?;
+
+    Ok(if negative { -amount } else { amount })
+}
+
+fn resolve_column(
+    headers: Option<&StringRecord>,
+    column: &ColumnRef,
+    field: &str,
+) -> Result<usize, FieldError> {
+    match (column, headers) {
+        (ColumnRef::Index(index), _) => Ok(*index),
+        (ColumnRef::Name(name), Some(headers)) => headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                FieldError::new(field, &format!("column '{name}' is not in the header!"))
+            }),
+        (ColumnRef::Name(_), None) => Err(FieldError::new(
+            field,
+            "columns can only be referenced by name when the file has a header!",
+        )),
+    }
+}
+
+fn cell(record: &StringRecord, column: Option<usize>) -> Option<&str> {
+    column
+        .and_then(|column| record.get(column))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+struct CsvColumns {
+    date: usize,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    description: Option<usize>,
+    category: Option<usize>,
+    currency: Option<usize>,
+}
+
+/// banks often write 0.00 into the debit or credit column they do not use, so a zero
+/// counts as blank there
+fn debit_credit_cell(
+    record: &StringRecord,
+    column: Option<usize>,
+    decimal_comma: bool,
+) -> Result<Option<Decimal>, String> {
+    cell(record, column)
+        .map(|value| parse_amount(value, decimal_comma))
+        .transpose()
+        .map(|amount| amount.filter(|amount| !amount.is_zero()))
+}
+
+/// signed from the account's point of view like every other statement, debits are
+/// money going out and credits money coming in
+fn parse_record(
+    row: usize,
+    record: &StringRecord,
+    columns: &CsvColumns,
+    mapping: &CsvMapping,
+    currency: &Option<String>,
+    seen: &mut HashMap<String, usize>,
+) -> ParsedRow {
+    let mut errors = Vec::new();
+
+    let transaction_date = match cell(record, Some(columns.date)) {
+        Some(value) => parse_date(value, mapping.date_format.as_deref())
+            .map_err(|error| errors.push(error))
+            .ok(),
+        None => {
+            errors.push("date is missing!".to_string());
+            None
+        }
+    };
+
+    let amount = if columns.amount.is_some() {
+        match cell(record, columns.amount) {
+            Some(value) => parse_amount(value, mapping.decimal_comma)
+                .map(|amount| {
+                    if mapping.invert_amounts {
+                        -amount
+                    } else {
+                        amount
+                    }
+                })
+                .map_err(|error| errors.push(error))
+                .ok(),
+            None => {
+                errors.push("amount is missing!".to_string());
+                None
+            }
+        }
+    } else {
+        match (
+            debit_credit_cell(record, columns.debit, mapping.decimal_comma),
+            debit_credit_cell(record, columns.credit, mapping.decimal_comma),
+        ) {
+            (Ok(Some(debit)), Ok(None)) => Some(-debit.abs()),
+            (Ok(None), Ok(Some(credit))) => Some(credit.abs()),
+            (Ok(Some(_)), Ok(Some(_))) => {
+                errors.push("only one of debit and credit may be filled in!".to_string());
+                None
+            }
+            (Ok(None), Ok(None)) => {
+                errors.push("amount is missing!".to_string());
+                None
+            }
+            (debit, credit) => {
+                errors.extend(debit.err());
+                errors.extend(credit.err());
+                None
+            }
+        }
+    };
+
+    let entry = StatementEntry {
+        transaction_date,
+        amount,
+        description: cell(record, columns.description).map(str::to_string),
+        category: cell(record, columns.category).map(str::to_string),
+        currency: cell(record, columns.currency)
+            .map(str::to_string)
+            .or_else(|| currency.clone()),
+    };
+    let defaults = StatementDefaults {
+        expense_category: mapping.expense_category.as_deref(),
+        income_category: mapping.income_category.as_deref(),
+        account_id: mapping.account_id,
+    };
+
+    // like QIF there are no ids, the row itself is the identity and identical rows in one
+    // file are told apart by their order
+    let key = format!(
+        "{}|{}",
+        record.iter().map(str::trim).collect::<Vec<_>>().join("|"),
+        mapping
+            .account_id
+            .map(|id| id.to_string())
+            .unwrap_or_default()
+    );
+    let ordinal = seen.entry(key.clone()).or_default();
+    *ordinal += 1;
+    let import_id = format!("csv:{}", content_hash(&format!("{key}|{ordinal}")));
+
+    statement_row(row, Some(import_id), entry, &defaults, errors)
+}
+
+pub fn parse_csv(data: &[u8], mapping: &CsvMapping) -> Result<Vec<ParsedRow>, GlobalAppError> {
+    let mut errors = Vec::new();
+
+    let delimiter = mapping.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        errors.push(FieldError::new(
+            "mapping.delimiter",
+            "delimiter must be a single ascii character!",
+        ));
+    }
+    let has_amount = mapping.amount_column.is_some();
+    let has_debit_credit = mapping.debit_column.is_some() || mapping.credit_column.is_some();
+    if has_amount == has_debit_credit {
+        errors.push(FieldError::new(
+            "mapping.amount_column",
+            "provide either amount_column or debit_column/credit_column!",
+        ));
+    }
+    if mapping.invert_amounts && !has_amount {
+        errors.push(FieldError::new(
+            "mapping.invert_amounts",
+            "invert_amounts only applies to amount_column!",
+        ));
+    }
+    let currency = mapping.currency.as_deref().and_then(|code| {
+        let currency = normalize_currency(code);
+        if currency.is_none() {
+            errors.push(FieldError::new(
+                "mapping.currency",
+                &format!("'{}' is not an ISO 4217 currency!", code.trim()),
+            ));
+        }
+        currency
+    });
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(mapping.has_header)
+        .delimiter(delimiter as u8)
+        .flexible(true)
+        .from_reader(data);
+
+    let headers = if mapping.has_header {
+        Some(
+            reader
+                .headers()
+                .map_err(|error| GlobalAppError::validation("file", &error.to_string()))?
+                .clone(),
+        )
+    } else {
+        None
+    };
+
+    let mut resolve = |column: &Option<ColumnRef>, field: &str| {
+        column.as_ref().and_then(|column| {
+            resolve_column(headers.as_ref(), column, field)
+                .map_err(|error| errors.push(error))
+                .ok()
+        })
+    };
+    let date = resolve(&Some(mapping.date_column.clone()), "mapping.date_column");
+    let amount = resolve(&mapping.amount_column, "mapping.amount_column");
+    let debit = resolve(&mapping.debit_column, "mapping.debit_column");
+    let credit = resolve(&mapping.credit_column, "mapping.credit_column");
+    let description = resolve(&mapping.description_column, "mapping.description_column");
+    let category = resolve(&mapping.category_column, "mapping.category_column");
+    let currency_column = resolve(&mapping.currency_column, "mapping.currency_column");
+
+    let Some(date) = date.filter(|_| errors.is_empty()) else {
+        return Err(GlobalAppError::Validation(errors));
+    };
+    let columns = CsvColumns {
+        date,
+        amount,
+        debit,
+        credit,
+        description,
+        category,
+        currency: currency_column,
+    };
+
+    let mut seen = HashMap::new();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        if rows.len() == MAX_IMPORT_ROWS {
+            return Err(too_many_rows());
+        }
+
+        rows.push(match record {
+            Ok(record) => parse_record(
+                record
+                    .position()
+                    .map_or(0, |position| position.line() as usize),
+                &record,
+                &columns,
+                mapping,
+                &currency,
+                &mut seen,
+            ),
+            Err(error) => ParsedRow {
+                row: error
+                    .position()
+                    .map_or(0, |position| position.line() as usize),
+                import_id: None,
+                category_type: None,
+                transaction: Err(vec![error.to_string()]),
+            },
+        });
+    }
+
+    Ok(rows)
+}
+
+fn too_many_rows() -> GlobalAppError {
+    GlobalAppError::validation(
+        "file",
+        &format!("a file may contain at most {MAX_IMPORT_ROWS} rows!"),
+    )
+}
+
+fn statement_currency(options: &StatementOptions) -> Result<Option<String>, GlobalAppError> {
+    options
+        .currency
+        .as_deref()
+        .map(|code| {
+            normalize_currency(code).ok_or_else(|| {
+                GlobalAppError::validation(
+                    "options.currency",
+                    &format!("'{}' is not an ISO 4217 currency!", code.trim()),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// what a statement says about one entry, already parsed where it could be
+struct StatementEntry {
+    transaction_date: Option<DateTime<Utc>>,
+    amount: Option<Decimal>,
+    description: Option<String>,
+    category: Option<String>,
+    currency: Option<String>,
+}
+
+/// where an entry goes when the file itself does not say
+struct StatementDefaults<'a> {
+    expense_category: Option<&'a str>,
+    income_category: Option<&'a str>,
+    account_id: Option<Uuid>,
+}
+
+impl<'a> From<&'a StatementOptions> for StatementDefaults<'a> {
+    fn from(options: &'a StatementOptions) -> Self {
+        Self {
+            expense_category: options.expense_category.as_deref(),
+            income_category: options.income_category.as_deref(),
+            account_id: options.account_id,
+        }
+    }
+}
+
+/// statements are signed from the account's point of view, so unless the file names a
+/// category the sign decides between the expense and the income category. either way the
+/// category has to be of the type the sign asks for, `import_rows` checks that
+fn statement_row(
+    row: usize,
+    import_id: Option<String>,
+    entry: StatementEntry,
+    defaults: &StatementDefaults,
+    mut errors: Vec<String>,
+) -> ParsedRow {
+    let category_type = entry.amount.map(|amount| {
+        if amount.is_sign_negative() {
+            CategoryType::Expense
+        } else {
+            CategoryType::Income
+        }
+    });
+    let category = entry.category.or_else(|| {
+        match category_type? {
+            CategoryType::Expense => defaults.expense_category,
+            CategoryType::Income => defaults.income_category,
+        }
+        .map(str::to_string)
+    });
+
+    match (entry.amount, category_type) {
+        (Some(amount), _) if amount.is_zero() => {
+            errors.push("amount must not be zero!".to_string())
+        }
+        (_, Some(CategoryType::Expense)) if category.is_none() => {
+            errors.push("category is missing and no expense_category was given!".to_string())
+        }
+        (_, Some(CategoryType::Income)) if category.is_none() => {
+            errors.push("category is missing and no income_category was given!".to_string())
+        }
+        _ => {}
+    }
+
+    let transaction = match (entry.transaction_date, entry.amount, category) {
+        (Some(transaction_date), Some(amount), Some(category)) if errors.is_empty() => {
+            Ok(TransactionRequest {
+                category,
+                description: entry.description,
+                transaction_date,
+                amount: amount.abs(),
+                currency: entry.currency,
+                account_id: defaults.account_id,
+            })
+        }
+        _ => Err(errors),
+    };
+
+    ParsedRow {
+        row,
+        import_id,
+        category_type,
+        transaction,
+    }
+}
+
+fn content_hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// OFX dates look like 20240115120000.000[-5:EST], everything after the day is optional
+/// and a missing offset means UTC
+fn parse_ofx_date(value: &str) -> Result<DateTime<Utc>, String> {
+    let invalid = || format!("'{value}' is not a valid date!");
+
+    let digits: String = value.chars().take_while(char::is_ascii_digit).collect();
+    if digits.len() < 8 {
+        return Err(invalid());
+    }
+    let date = NaiveDate::parse_from_str(&digits[..8], "%Y%m%d").map_err(|_| invalid())?;
+    let time = match digits.get(8..14) {
+        Some(time) => NaiveTime::parse_from_str(time, "%H%M%S").map_err(|_| invalid())?,
+        None => NaiveTime::MIN,
+    };
+
+    let offset_hours = match value.split_once('[') {
+        Some((_, zone)) => zone
+            .trim_end_matches(']')
+            .split(':')
+            .next()
+            .and_then(|hours| hours.trim().parse::<f64>().ok())
+            .ok_or_else(invalid)?,
+        None => 0.0,
+    };
+    let offset = TimeDelta::seconds((offset_hours * 3600.0) as i64);
+
+    Ok(date.and_time(time).and_utc() - offset)
+}
+
+fn ofx_entry(
+    row: usize,
+    fields: &HashMap<String, String>,
+    account: &str,
+    currency: Option<String>,
+    options: &StatementOptions,
+) -> ParsedRow {
+    let mut errors = Vec::new();
+    let field = |name: &str| fields.get(name).map(String::as_str);
+
+    let transaction_date = match field("DTPOSTED") {
+        Some(value) => parse_ofx_date(value)
+            .map_err(|error| errors.push(error))
+            .ok(),
+        None => {
+            errors.push("DTPOSTED is missing!".to_string());
+            None
+        }
+    };
+    let amount = match field("TRNAMT") {
+        // a few banks write decimal commas even though the spec asks for points
+        Some(value) => parse_amount(value, value.contains(',') && !value.contains('.'))
+            .map_err(|error| errors.push(error))
+            .ok(),
+        None => {
+            errors.push("TRNAMT is missing!".to_string());
+            None
+        }
+    };
+    let import_id = match field("FITID") {
+        Some(fitid) => Some(format!("ofx:{account}:{fitid}")),
+        None => {
+            errors.push("FITID is missing!".to_string());
+            None
+        }
+    };
+
+    let entry = StatementEntry {
+        transaction_date,
+        amount,
+        description: field("NAME").or(field("MEMO")).map(str::to_string),
+        category: None,
+        currency,
+    };
+
+    statement_row(row, import_id, entry, &options.into(), errors)
+}
+
+/// pulls the transactions out of an OFX or QFX statement, the SGML flavour of OFX 1.x
+/// leaves most closing tags out so the file is read as a flat run of tags and values
+pub fn parse_ofx(
+    data: &[u8],
+    options: &StatementOptions,
+) -> Result<Vec<ParsedRow>, GlobalAppError> {
+    let currency = statement_currency(options)?;
+    let text = String::from_utf8_lossy(data);
+    let Some(start) = text.to_ascii_uppercase().find("<OFX>") else {
+        return Err(GlobalAppError::validation(
+            "file",
+            "file is not an OFX statement!",
+        ));
+    };
+
+    let mut line = text[..start].matches('\n').count() + 1;
+    let mut rest = &text[start..];
+    let mut account = String::new();
+    let mut default_currency = None;
+    let mut entry: Option<(usize, HashMap<String, String>)> = None;
+    let mut rows = Vec::new();
+
+    while let Some(open) = rest.find('<') {
+        line += rest[..open].matches('\n').count();
+        let tag_start = &rest[open + 1..];
+        let Some(close) = tag_start.find('>') else {
+            break;
+        };
+        let tag = tag_start[..close]
+            .trim()
+            .trim_end_matches('/')
+            .to_ascii_uppercase();
+        line += tag_start[..close].matches('\n').count();
+        rest = &tag_start[close + 1..];
+        let value = decode_entities(rest[..rest.find('<').unwrap_or(rest.len())].trim());
+
+        match tag.as_str() {
+            "STMTTRN" => entry = Some((line, HashMap::new())),
+            "/STMTTRN" => {
+                let Some((row, fields)) = entry.take() else {
+                    continue;
+                };
+                if rows.len() == MAX_IMPORT_ROWS {
+                    return Err(too_many_rows());
+                }
+                let entry_currency = currency.clone().or_else(|| default_currency.clone());
+                rows.push(ofx_entry(row, &fields, &account, entry_currency, options));
+            }
+            _ if value.is_empty() => {}
+            _ => match &mut entry {
+                // the first value wins, nested aggregates like PAYEE repeat a few names
+                Some((_, fields)) => {
+                    fields.entry(tag).or_insert(value);
+                }
+                None if tag == "ACCTID" => account = value,
+                None if tag == "CURDEF" => default_currency = normalize_currency(&value),
+                None => {}
+            },
+        }
+    }
+
+    Ok(rows)
+}
+
+/// QIF leaves the date format to the exporting program, Quicken writes 1/15'24 or
+/// 1/15/2024 and a few others write 2024-01-15 or 15.01.2024
+fn parse_qif_date(value: &str, format: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let normalized = value.trim().replace('\'', "/").replace(' ', "");
+    let format = format.unwrap_or(if normalized.contains('.') {
+        "%d.%m.%Y"
+    } else if normalized.contains('-') {
+        "%Y-%m-%d"
+    } else if normalized
+        .rsplit('/')
+        .next()
+        .is_some_and(|year| year.len() <= 2)
+    {
+        "%m/%d/%y"
+    } else {
+        "%m/%d/%Y"
+    });
+
+    parse_date(&normalized, Some(format))
+        .map_err(|_| format!("'{}' is not a valid date!", value.trim()))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum QifSection {
+    Transactions,
+    Investments,
+    /// account lists, categories, classes and memorized transactions
+    Other,
+}
+
+fn qif_section(header: &str) -> QifSection {
+    match header
+        .trim()
+        .to_ascii_lowercase()
+        .replace(": ", ":")
+        .as_str()
+    {
+        "type:bank" | "type:cash" | "type:ccard" | "type:oth a" | "type:oth l" => {
+            QifSection::Transactions
+        }
+        "type:invst" => QifSection::Investments,
+        _ => QifSection::Other,
+    }
+}
+
+/// the first value of every field, splits repeat S, E and $ per line and are left out
+fn qif_entry(
+    row: usize,
+    fields: &[(char, &str)],
+    currency: &Option<String>,
+    options: &StatementOptions,
+    seen: &mut HashMap<String, usize>,
+) -> ParsedRow {
+    let mut errors = Vec::new();
+    let field = |code: char| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == code)
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+
+    let date = field('D');
+    let amount = field('T').or(field('U'));
+    let transaction_date = match date {
+        Some(value) => parse_qif_date(value, options.date_format.as_deref())
+            .map_err(|error| errors.push(error))
+            .ok(),
+        None => {
+            errors.push("date is missing!".to_string());
+            None
+        }
+    };
+    let parsed_amount = match amount {
+        Some(value) => parse_amount(value, options.decimal_comma)
+            .map_err(|error| errors.push(error))
+            .ok(),
+        None => {
+            errors.push("amount is missing!".to_string());
+            None
+        }
+    };
+
+    // [Checking] is a transfer to another account, Food:Groceries/Class a subcategory
+    let category = field('L')
+        .filter(|category| !category.starts_with('['))
+        .and_then(|category| category.split([':', '/']).next())
+        .map(str::trim)
+        .filter(|category| !category.is_empty())
+        .map(str::to_string);
+
+    let description = match (field('P'), field('M')) {
+        (Some(payee), _) => Some(payee.to_string()),
+        (None, memo) => memo.map(str::to_string),
+    };
+
+    // QIF has no ids, identical entries in one file are told apart by their order
+    let key = [date, amount, field('P'), field('M'), field('N'), field('L')]
+        .map(|value| value.unwrap_or_default())
+        .join("|");
+    let key = format!(
+        "{key}|{}",
+        options
+            .account_id
+            .map(|id| id.to_string())
+            .unwrap_or_default()
+    );
+    let ordinal = seen.entry(key.clone()).or_default();
+    *ordinal += 1;
+    let import_id = format!("qif:{}", content_hash(&format!("{key}|{ordinal}")));
+
+    let entry = StatementEntry {
+        transaction_date,
+        amount: parsed_amount,
+        description,
+        category,
+        currency: currency.clone(),
+    };
+
+    statement_row(row, Some(import_id), entry, &options.into(), errors)
+}
+
+/// turns the fields collected since the last record into a row, nothing when there are none
+fn push_qif_record(
+    rows: &mut Vec<ParsedRow>,
+    section: QifSection,
+    row: usize,
+    fields: &[(char, &str)],
+    currency: &Option<String>,
+    options: &StatementOptions,
+    seen: &mut HashMap<String, usize>,
+) -> Result<(), GlobalAppError> {
+    if fields.is_empty() || section == QifSection::Other {
+        return Ok(());
+    }
+    if rows.len() == MAX_IMPORT_ROWS {
+        return Err(too_many_rows());
+    }
+
+    rows.push(match section {
+        QifSection::Investments => ParsedRow {
+            row,
+            import_id: None,
+            category_type: None,
+            transaction: Err(vec!["investment entries are not supported!".to_string()]),
+        },
+        _ => qif_entry(row, fields, currency, options, seen),
+    });
+
+    Ok(())
+}
+
+pub fn parse_qif(
+    data: &[u8],
+    options: &StatementOptions,
+) -> Result<Vec<ParsedRow>, GlobalAppError> {
+    let currency = statement_currency(options)?;
+    let text = String::from_utf8_lossy(data);
+
+    // files without a header are plain bank registers
+    let mut section = QifSection::Transactions;
+    let mut fields: Vec<(char, &str)> = Vec::new();
+    let mut row = 0;
+    let mut seen = HashMap::new();
+    let mut rows = Vec::new();
+
+    let mut lines = text.lines().enumerate().peekable();
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim_start_matches('\u{feff}');
+        let end_of_record = line.starts_with('^') || lines.peek().is_none();
+
+        if let Some(header) = line.strip_prefix('!') {
+            // a record the file did not close with ^ still belongs to the old section
+            push_qif_record(
+                &mut rows, section, row, &fields, &currency, options, &mut seen,
+            )?;
+            section = qif_section(header);
+            fields.clear();
+            continue;
+        }
+        if !line.starts_with('^') && !line.trim().is_empty() {
+            if fields.is_empty() {
+                row = index + 1;
+            }
+            let mut chars = line.chars();
+            if let Some(code) = chars.next() {
+                fields.push((code.to_ascii_uppercase(), chars.as_str()));
+            }
+        }
+        if !end_of_record {
+            continue;
+        }
+
+        push_qif_record(
+            &mut rows, section, row, &fields, &currency, options, &mut seen,
+        )?;
+        fields.clear();
+    }
+
+    Ok(rows)
+}
+
+/// a row going out of the account can only be booked on an expense category and one
+/// coming in only on an income category
+async fn ensure_category_type(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    category: &str,
+    category_type: Option<CategoryType>,
+) -> Result<(), GlobalAppError> {
+    let Some(category_type) = category_type else {
+        return Ok(());
+    };
+
+    let category = category_by_slug(conn, user_id, category).await?;
+    match (category.category_type, category_type) {
+        (CategoryType::Expense, CategoryType::Income) => Err(GlobalAppError::validation(
+            "category",
+            &format!(
+                "'{}' is an expense category but the money came in!",
+                category.name
+            ),
+        )),
+        (CategoryType::Income, CategoryType::Expense) => Err(GlobalAppError::validation(
+            "category",
+            &format!(
+                "'{}' is an income category but the money went out!",
+                category.name
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// stores the valid rows through `insert_transaction`, each in its own savepoint so a bad
+/// row is reported without losing the others. rows whose import id is already taken,
+/// earlier or further up the same file, are reported as duplicates. nothing is kept in a preview
+pub async fn import_rows(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    rows: Vec<ParsedRow>,
+    preview: bool,
+    window_days: i32,
+) -> Result<ImportResponse, GlobalAppError> {
+    let mut tx = conn.begin().await?;
+    let mut results = Vec::with_capacity(rows.len());
+
+    for ParsedRow {
+        row,
+        import_id,
+        category_type,
+        transaction,
+    } in rows
+    {
+        let transaction = match transaction {
+            Ok(transaction) => transaction,
+            Err(errors) => {
+                results.push(ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Invalid,
+                    transaction: None,
+                    transaction_id: None,
+                    possible_duplicates: Vec::new(),
+                    errors,
+                });
+                continue;
+            }
+        };
+
+        if let Some(import_id) = &import_id {
+            let (duplicate,) = query_as::<_, (bool,)>(
+                "SELECT EXISTS (SELECT 1 FROM transactions WHERE user_id = $1 AND import_id = $2)",
+            )
+            .bind(user_id)
+            .bind(import_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if duplicate {
+                results.push(ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Duplicate,
+                    transaction: Some(transaction),
+                    transaction_id: None,
+                    possible_duplicates: Vec::new(),
+                    errors: Vec::new(),
+                });
+                continue;
+            }
+        }
+
+        let mut savepoint = tx.begin().await?;
+        let inserted = match ensure_category_type(
+            &mut savepoint,
+            user_id,
+            &transaction.category,
+            category_type,
+        )
+        .await
+        {
+            Ok(()) => {
+                insert_transaction(&mut savepoint, user_id, &transaction, import_id.as_deref())
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+        match inserted {
+            Ok(transaction_id) => {
+                let duplicates =
+                    possible_duplicates(&mut savepoint, user_id, transaction_id, window_days)
+                        .await?;
+                savepoint.commit().await?;
+                results.push(ImportRowResult {
+                    row,
+                    status: if preview {
+                        ImportRowStatus::Valid
+                    } else {
+                        ImportRowStatus::Imported
+                    },
+                    transaction: Some(transaction),
+                    transaction_id: (!preview).then_some(transaction_id),
+                    possible_duplicates: duplicates,
+                    errors: Vec::new(),
+                });
+            }
+            Err(error) => {
+                savepoint.rollback().await?;
+                results.push(ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Invalid,
+                    transaction: Some(transaction),
+                    transaction_id: None,
+                    possible_duplicates: Vec::new(),
+                    errors: vec![error.message()],
+                });
+            }
+        }
+    }
+
+    if preview {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    let count = |status| {
+        results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    };
+    let invalid = count(ImportRowStatus::Invalid);
+    let duplicates = count(ImportRowStatus::Duplicate);
+
+    Ok(ImportResponse {
+        preview,
+        total_rows: results.len(),
+        imported: results.len() - invalid - duplicates,
+        duplicates,
+        invalid,
+        rows: results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    fn mapping(json: &str) -> CsvMapping {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// category, amount and expected category type of every row, or its errors
+    fn summary(rows: &[ParsedRow]) -> Vec<Result<(String, Decimal, CategoryType), Vec<String>>> {
+        rows.iter()
+            .map(|row| match &row.transaction {
+                Ok(transaction) => Ok((
+                    transaction.category.clone(),
+                    transaction.amount,
+                    row.category_type.unwrap(),
+                )),
+                Err(errors) => Err(errors.clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_amount_handles_bank_formats() {
+        assert_eq!(parse_amount("$1,234.50", false), Ok(dec!(1234.50)));
+        assert_eq!(parse_amount("1.234,50 €", true), Ok(dec!(1234.50)));
+        assert_eq!(parse_amount("(12.50)", false), Ok(dec!(-12.50)));
+        assert_eq!(parse_amount("-7", false), Ok(dec!(-7)));
+        assert!(parse_amount("n/a", false).is_err());
+    }
+
+    #[test]
+    fn signed_amounts_pick_the_category_by_direction() {
+        let data =
+            b"date,amount,description\n2024-01-15,-12.50,coffee\n2024-01-31,2000.00,salary\n";
+        let mapping = mapping(
+            r#"{"date_column": "date", "amount_column": "amount", "description_column": "description",
+                "expense_category": "food", "income_category": "salary"}"#,
+        );
+
+        let rows = parse_csv(data, &mapping).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("food".to_string(), dec!(12.50), CategoryType::Expense)),
+                Ok(("salary".to_string(), dec!(2000.00), CategoryType::Income)),
+            ]
+        );
+        assert_eq!(rows[0].row, 2);
+    }
+
+    #[test]
+    fn invert_amounts_flips_the_direction() {
+        let data = b"date,amount\n2024-01-15,25.00\n2024-01-16,-10.00\n";
+        let mapping = mapping(
+            r#"{"date_column": 0, "amount_column": 1, "invert_amounts": true,
+                "expense_category": "food", "income_category": "refunds"}"#,
+        );
+
+        let rows = parse_csv(data, &mapping).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("food".to_string(), dec!(25.00), CategoryType::Expense)),
+                Ok(("refunds".to_string(), dec!(10.00), CategoryType::Income)),
+            ]
+        );
+    }
+
+    #[test]
+    fn invert_amounts_needs_an_amount_column() {
+        let mapping = mapping(
+            r#"{"date_column": 0, "debit_column": 1, "credit_column": 2, "invert_amounts": true}"#,
+        );
+
+        assert!(parse_csv(b"date,debit,credit\n", &mapping).is_err());
+    }
+
+    #[test]
+    fn csv_import_ids_are_stable_and_tell_identical_rows_apart() {
+        let mapping =
+            mapping(r#"{"date_column": 0, "amount_column": 1, "expense_category": "food"}"#);
+        let first = parse_csv(b"date,amount\n2024-01-15,-5\n2024-01-15,-5\n", &mapping).unwrap();
+        let second = parse_csv(
+            b"date,amount\n2024-01-14,-9\n2024-01-15,-5\n2024-01-15,-5\n",
+            &mapping,
+        )
+        .unwrap();
+
+        assert!(first[0].import_id.as_deref().unwrap().starts_with("csv:"));
+        assert_ne!(first[0].import_id, first[1].import_id);
+        assert_eq!(first[0].import_id, second[1].import_id);
+        assert_eq!(first[1].import_id, second[2].import_id);
+    }
+
+    #[test]
+    fn category_column_still_carries_the_direction() {
+        let data = b"date,amount,category\n2024-01-15,25.00,food\n";
+        let mapping = mapping(r#"{"date_column": 0, "amount_column": 1, "category_column": 2}"#);
+
+        let rows = parse_csv(data, &mapping).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![Ok(("food".to_string(), dec!(25.00), CategoryType::Income))]
+        );
+    }
+
+    #[test]
+    fn debit_and_credit_columns_ignore_zero_cells() {
+        let data = b"date,debit,credit\n\
+            2024-01-15,12.50,0.00\n\
+            2024-01-16,0.00,50.00\n\
+            2024-01-17,,30.00\n\
+            2024-01-18,5.00,5.00\n\
+            2024-01-19,0.00,\n";
+        let mapping = mapping(
+            r#"{"date_column": "date", "debit_column": "debit", "credit_column": "credit",
+                "expense_category": "food", "income_category": "salary"}"#,
+        );
+
+        let rows = parse_csv(data, &mapping).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("food".to_string(), dec!(12.50), CategoryType::Expense)),
+                Ok(("salary".to_string(), dec!(50.00), CategoryType::Income)),
+                Ok(("salary".to_string(), dec!(30.00), CategoryType::Income)),
+                Err(vec![
+                    "only one of debit and credit may be filled in!".to_string()
+                ]),
+                Err(vec!["amount is missing!".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_default_is_reported_per_direction() {
+        let data = b"date,amount\n2024-01-15,-12.50\n2024-01-16,0\n";
+        let mapping = mapping(
+            r#"{"date_column": "date", "amount_column": "amount", "income_category": "salary"}"#,
+        );
+
+        let rows = parse_csv(data, &mapping).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Err(vec![
+                    "category is missing and no expense_category was given!".to_string()
+                ]),
+                Err(vec!["amount must not be zero!".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn mapping_needs_exactly_one_kind_of_amount_column() {
+        let mapping = mapping(
+            r#"{"date_column": "date", "amount_column": "amount", "debit_column": "debit"}"#,
+        );
+
+        assert!(parse_csv(b"date,amount,debit\n", &mapping).is_err());
+    }
+
+    fn options(json: &str) -> StatementOptions {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn ofx_dates_honour_the_offset() {
+        assert_eq!(
+            parse_ofx_date("20240115120000.000[-5:EST]").unwrap(),
+            "2024-01-15T17:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            parse_ofx_date("20240115").unwrap(),
+            "2024-01-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(parse_ofx_date("2024").is_err());
+    }
+
+    #[test]
+    fn ofx_sgml_statement_is_parsed() {
+        let data = b"OFXHEADER:100\nDATA:OFXSGML\n\n<OFX>\n<BANKMSGSRSV1><STMTTRNRS><STMTRS>\n\
+            <CURDEF>EUR\n<BANKACCTFROM><ACCTID>12345\n</BANKACCTFROM>\n<BANKTRANLIST>\n\
+            <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20240115\n<TRNAMT>-12.50\n<FITID>A1\n<NAME>Coffee &amp; Cake\n</STMTTRN>\n\
+            <STMTTRN>\n<TRNTYPE>CREDIT\n<DTPOSTED>20240131\n<TRNAMT>2000.00\n<FITID>A2\n<NAME>Salary\n</STMTTRN>\n\
+            <STMTTRN>\n<DTPOSTED>20240201\n<TRNAMT>1.00\n</STMTTRN>\n\
+            </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n";
+        let options = options(r#"{"expense_category": "food", "income_category": "salary"}"#);
+
+        let rows = parse_ofx(data, &options).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("food".to_string(), dec!(12.50), CategoryType::Expense)),
+                Ok(("salary".to_string(), dec!(2000.00), CategoryType::Income)),
+                Err(vec!["FITID is missing!".to_string()]),
+            ]
+        );
+        assert_eq!(rows[0].import_id.as_deref(), Some("ofx:12345:A1"));
+        let transaction = rows[0].transaction.as_ref().unwrap();
+        assert_eq!(transaction.description.as_deref(), Some("Coffee & Cake"));
+        assert_eq!(transaction.currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn ofx_needs_an_ofx_tag() {
+        assert!(parse_ofx(b"date,amount\n", &StatementOptions::default()).is_err());
+    }
+
+    #[test]
+    fn qif_bank_register_is_parsed() {
+        let data = b"!Type:Bank\nD1/15'24\nT-12.50\nPCoffee\nLFood:Coffee\n^\n\
+            D01/31/2024\nT2,000.00\nPEmployer\n^\n\
+            D01/31/2024\nT2,000.00\nPEmployer\n^\n\
+            D02/01/2024\nT-50.00\nL[Savings]\n^\n";
+        let options = options(r#"{"expense_category": "misc", "income_category": "salary"}"#);
+
+        let rows = parse_qif(data, &options).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("Food".to_string(), dec!(12.50), CategoryType::Expense)),
+                Ok(("salary".to_string(), dec!(2000.00), CategoryType::Income)),
+                Ok(("salary".to_string(), dec!(2000.00), CategoryType::Income)),
+                Ok(("misc".to_string(), dec!(50.00), CategoryType::Expense)),
+            ]
+        );
+        assert_eq!(
+            rows.iter().map(|row| row.row).collect::<Vec<_>>(),
+            vec![2, 7, 11, 15]
+        );
+        // identical entries in one file stay apart
+        assert_ne!(rows[1].import_id, rows[2].import_id);
+        let transaction = rows[0].transaction.as_ref().unwrap();
+        assert_eq!(
+            transaction.transaction_date,
+            "2024-01-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn qif_skips_lists_and_rejects_investments() {
+        let data = b"!Type:Cat\nNFood\nE\n^\n!Type:Invst\nD01/15/2024\nNBuy\nT100.00\n^\n";
+
+        let rows = parse_qif(data, &StatementOptions::default()).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![Err(vec![
+                "investment entries are not supported!".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn qif_records_left_open_before_a_header_are_kept() {
+        let data =
+            b"!Type:Bank\nD01/15/2024\nT-12.50\nPCoffee\n!Type:CCard\nD01/16/2024\nT-3.00\n^\n";
+        let options = options(r#"{"expense_category": "food"}"#);
+
+        let rows = parse_qif(data, &options).unwrap();
+
+        assert_eq!(
+            summary(&rows),
+            vec![
+                Ok(("food".to_string(), dec!(12.50), CategoryType::Expense)),
+                Ok(("food".to_string(), dec!(3.00), CategoryType::Expense)),
+            ]
+        );
+        assert_eq!(
+            rows.iter().map(|row| row.row).collect::<Vec<_>>(),
+            vec![2, 6]
+        );
+    }
+
+    #[test]
+    fn qif_import_ids_are_stable_across_files() {
+        let data = b"!Type:Bank\nD01/15/2024\nT-12.50\nPCoffee\n^\n";
+        let options = options(r#"{"expense_category": "food"}"#);
+
+        let first = parse_qif(data, &options).unwrap();
+        let second = parse_qif(data, &options).unwrap();
+
+        assert_eq!(first[0].import_id, second[0].import_id);
+    }
+}
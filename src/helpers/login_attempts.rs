@@ -0,0 +1,289 @@
+use std::net::SocketAddr;
+
+use axum::http::{HeaderMap, header};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Connection, PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    models::login_attempts::{LoginAttemptInfo, LoginContext, LoginFailureRow, LoginOutcome},
+};
+
+/// failures older than this are forgotten
+pub const LOGIN_FAILURE_WINDOW_MINUTES: i64 = 60;
+/// failures allowed before backing off, per submitted username
+pub const FREE_FAILURES_PER_USERNAME: i64 = 5;
+/// failures allowed before backing off, per client address across all usernames
+pub const FREE_FAILURES_PER_IP: i64 = 20;
+/// the backoff never grows past this, reaching it is a temporary lockout
+pub const LOCKOUT_MINUTES: i64 = 15;
+pub const RECENT_LOGINS_LIMIT: i64 = 20;
+
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// the same error for unknown usernames and wrong passwords, so it tells nobody which
+/// usernames exist
+pub fn invalid_credentials() -> GlobalAppError {
+    GlobalAppError::Unauthorized("invalid username or password!".to_string())
+}
+
+impl LoginContext {
+    /// X-Forwarded-For is only believed when the server sits behind a proxy that sets it,
+    /// otherwise any client could pick a fresh address for every attempt
+    pub fn from_request(headers: &HeaderMap, peer: SocketAddr, trust_forwarded_for: bool) -> Self {
+        let forwarded_for = trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+            .flatten()
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+
+        Self {
+            ip_address: forwarded_for
+                .map(str::to_string)
+                .unwrap_or_else(|| peer.ip().to_string()),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+        }
+    }
+}
+
+/// none until `free` failures, then one second doubling with every failure up to the lockout
+fn backoff(failures: i64, free: i64) -> Duration {
+    let lockout = Duration::minutes(LOCKOUT_MINUTES);
+    match failures - free {
+        ..0 => Duration::zero(),
+        doublings @ 0..20 => Duration::seconds(1 << doublings).min(lockout),
+        _ => lockout,
+    }
+}
+
+fn retry_after(row: &LoginFailureRow, free: i64, now: DateTime<Utc>) -> Duration {
+    row.last_failure_at
+        .map_or(Duration::zero(), |last_failure_at| {
+            last_failure_at + backoff(row.failures, free) - now
+        })
+}
+
+/// seconds until the next attempt for this username from this address is allowed,
+/// None when it may go ahead right away. a successful login clears the username's
+/// failures but not the address's, which may be guessing at many accounts
+async fn login_retry_after(
+    conn: &mut PgConnection,
+    username: &str,
+    context: &LoginContext,
+) -> Result<Option<u64>, GlobalAppError> {
+    let now = Utc::now();
+    let since = now - Duration::minutes(LOGIN_FAILURE_WINDOW_MINUTES);
+
+    let by_username = query_as::<_, LoginFailureRow>(
+        r#"SELECT COUNT(*) AS failures, MAX(created_at) AS last_failure_at
+        FROM login_attempts
+        WHERE username = $1
+        AND outcome IN ('invalid_credentials', 'invalid_two_factor', 'pending')
+        AND created_at > GREATEST($2, (
+            SELECT MAX(created_at) FROM login_attempts WHERE username = $1 AND outcome = 'success'
+        ))"#,
+    )
+    .bind(username)
+    .bind(since)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let by_ip = query_as::<_, LoginFailureRow>(
+        r#"SELECT COUNT(*) AS failures, MAX(created_at) AS last_failure_at
+        FROM login_attempts
+        WHERE ip_address = $1
+        AND outcome IN ('invalid_credentials', 'invalid_two_factor', 'pending')
+        AND created_at > $2"#,
+    )
+    .bind(&context.ip_address)
+    .bind(since)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let wait = retry_after(&by_username, FREE_FAILURES_PER_USERNAME, now).max(retry_after(
+        &by_ip,
+        FREE_FAILURES_PER_IP,
+        now,
+    ));
+
+    // round up so a client that waits exactly this long is let through
+    Ok((wait > Duration::zero()).then(|| (wait.num_milliseconds() as u64).div_ceil(1000)))
+}
+
+/// holds back every other login for `username` until the transaction ends, so attempts
+/// sent in parallel cannot all pass the throttle on the same failure count
+async fn lock_login_attempts(
+    conn: &mut PgConnection,
+    username: &str,
+) -> Result<(), GlobalAppError> {
+    query("SELECT pg_advisory_xact_lock(hashtext($1))")
+        .bind(username)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn record_login_attempt(
+    conn: &mut PgConnection,
+    user_id: Option<Uuid>,
+    username: &str,
+    context: &LoginContext,
+    outcome: LoginOutcome,
+) -> Result<Uuid, GlobalAppError> {
+    // the time of the attempt itself, NOW() would be when the surrounding transaction
+    // started, before it waited for `lock_login_attempts`
+    let (attempt_id,) = query_as::<_, (Uuid,)>(
+        "INSERT INTO login_attempts (user_id, username, ip_address, user_agent, outcome, created_at) VALUES ($1, $2, $3, $4, $5, clock_timestamp()) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(username)
+    .bind(&context.ip_address)
+    .bind(context.user_agent.as_deref())
+    .bind(outcome)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(attempt_id)
+}
+
+/// checks the throttle and records the attempt as pending in one short transaction,
+/// so attempts sent in parallel count against each other before any password or code
+/// is checked, and no lock or connection has to be held while it is. the returned id
+/// is settled with `settle_login_attempt`, one that never is keeps counting as a failure
+pub async fn begin_login_attempt(
+    conn: &mut PgConnection,
+    user_id: Option<Uuid>,
+    username: &str,
+    context: &LoginContext,
+) -> Result<Uuid, GlobalAppError> {
+    let mut tx = conn.begin().await?;
+    lock_login_attempts(&mut tx, username).await?;
+
+    if let Some(retry_after_secs) = login_retry_after(&mut tx, username, context).await? {
+        record_login_attempt(&mut tx, user_id, username, context, LoginOutcome::Throttled).await?;
+        tx.commit().await?;
+        return Err(GlobalAppError::RateLimited {
+            message: "too many failed login attempts! try again later".to_string(),
+            retry_after_secs,
+        });
+    }
+
+    let attempt_id =
+        record_login_attempt(&mut tx, user_id, username, context, LoginOutcome::Pending).await?;
+    tx.commit().await?;
+
+    Ok(attempt_id)
+}
+
+pub async fn settle_login_attempt(
+    conn: &mut PgConnection,
+    attempt_id: Uuid,
+    outcome: LoginOutcome,
+) -> Result<(), GlobalAppError> {
+    query("UPDATE login_attempts SET outcome = $1, created_at = clock_timestamp() WHERE id = $2")
+        .bind(outcome)
+        .bind(attempt_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// for checks that throttle like a login but are none, one that passes leaves nothing
+/// behind and does not clear earlier failures the way a successful login would
+pub async fn discard_login_attempt(
+    conn: &mut PgConnection,
+    attempt_id: Uuid,
+) -> Result<(), GlobalAppError> {
+    query("DELETE FROM login_attempts WHERE id = $1")
+        .bind(attempt_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn recent_logins(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Vec<LoginAttemptInfo>, GlobalAppError> {
+    Ok(query_as::<_, LoginAttemptInfo>(
+        r#"SELECT outcome, ip_address, user_agent, created_at
+        FROM login_attempts
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2"#,
+    )
+    .bind(user_id)
+    .bind(RECENT_LOGINS_LIMIT)
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failures(failures: i64, last_failure_at: Option<DateTime<Utc>>) -> LoginFailureRow {
+        LoginFailureRow {
+            failures,
+            last_failure_at,
+        }
+    }
+
+    #[test]
+    fn backoff_starts_after_the_free_failures_and_doubles() {
+        assert_eq!(backoff(0, 5), Duration::zero());
+        assert_eq!(backoff(4, 5), Duration::zero());
+        assert_eq!(backoff(5, 5), Duration::seconds(1));
+        assert_eq!(backoff(6, 5), Duration::seconds(2));
+        assert_eq!(backoff(9, 5), Duration::seconds(16));
+    }
+
+    #[test]
+    fn backoff_is_capped_at_the_lockout() {
+        let lockout = Duration::minutes(LOCKOUT_MINUTES);
+
+        assert_eq!(backoff(5 + 10, 5), lockout);
+        assert_eq!(backoff(5 + 19, 5), lockout);
+        assert_eq!(backoff(5 + 64, 5), lockout);
+        assert_eq!(backoff(i64::MAX, 5), lockout);
+    }
+
+    #[test]
+    fn retry_after_counts_from_the_last_failure() {
+        let now = Utc::now();
+
+        assert_eq!(
+            retry_after(&failures(7, Some(now - Duration::seconds(1))), 5, now),
+            Duration::seconds(3)
+        );
+        assert!(
+            retry_after(&failures(7, Some(now - Duration::seconds(10))), 5, now)
+                <= Duration::zero()
+        );
+        assert!(retry_after(&failures(3, Some(now)), 5, now) <= Duration::zero());
+        assert_eq!(retry_after(&failures(0, None), 5, now), Duration::zero());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_when_configured() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7, 10.0.0.1".parse().unwrap());
+        headers.insert(header::USER_AGENT, "curl/8.0".parse().unwrap());
+        let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+
+        let trusted = LoginContext::from_request(&headers, peer, true);
+        let untrusted = LoginContext::from_request(&headers, peer, false);
+
+        assert_eq!(trusted.ip_address, "203.0.113.7");
+        assert_eq!(untrusted.ip_address, "10.0.0.1");
+        assert_eq!(untrusted.user_agent.as_deref(), Some("curl/8.0"));
+    }
+}
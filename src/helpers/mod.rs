@@ -1 +1,15 @@
+pub mod accounts;
+pub mod categories;
+pub mod currencies;
+pub mod exports;
+pub mod imports;
+pub mod login_attempts;
+pub mod password_policy;
+pub mod portability;
+pub mod recurring;
+pub mod sessions;
+pub mod transactions;
+pub mod transfers;
+pub mod two_factor;
 pub mod users;
+pub mod verification;
@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    helpers::users::normalize_identifier,
+};
+
+/// the most used passwords from public breach corpora, matched after undoing letter
+/// substitutions. the breached password list catches the rest when it is configured
+const COMMON_PASSWORDS: &[&str] = &[
+    "password",
+    "123456",
+    "12345678",
+    "123456789",
+    "1234567890",
+    "qwerty",
+    "qwertyuiop",
+    "abc123",
+    "111111",
+    "000000",
+    "123123",
+    "654321",
+    "666666",
+    "121212",
+    "696969",
+    "football",
+    "baseball",
+    "basketball",
+    "soccer",
+    "hockey",
+    "monkey",
+    "letmein",
+    "dragon",
+    "iloveyou",
+    "trustno1",
+    "sunshine",
+    "master",
+    "welcome",
+    "shadow",
+    "ashley",
+    "michael",
+    "jessica",
+    "jennifer",
+    "jordan",
+    "ninja",
+    "mustang",
+    "superman",
+    "batman",
+    "spiderman",
+    "princess",
+    "starwars",
+    "whatever",
+    "freedom",
+    "hello",
+    "charlie",
+    "donald",
+    "login",
+    "admin",
+    "administrator",
+    "qazwsx",
+    "zaq1zaq1",
+    "access",
+    "flower",
+    "loveme",
+    "secret",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "hunter",
+    "killer",
+    "harley",
+    "ranger",
+    "buster",
+    "thomas",
+    "tigger",
+    "robert",
+    "daniel",
+    "andrew",
+    "joshua",
+    "pepper",
+    "maggie",
+    "ginger",
+    "cheese",
+    "computer",
+    "internet",
+    "cookie",
+    "chocolate",
+    "pokemon",
+    "naruto",
+    "matrix",
+    "samsung",
+    "apple",
+    "google",
+    "orange",
+    "banana",
+    "purple",
+    "silver",
+    "golden",
+    "diamond",
+    "angel",
+    "lovely",
+    "blink182",
+    "liverpool",
+    "chelsea",
+    "arsenal",
+    "yankees",
+    "cowboys",
+    "changeme",
+    "default",
+    "guest",
+    "root",
+    "test",
+    "demo",
+    "pass",
+    "money",
+    "family",
+    "friends",
+    "forever",
+    "expense",
+    "tracker",
+    "budget",
+];
+
+/// characters next to each other in these count as a pattern, in either direction
+const SEQUENCES: &[&str] = &[
+    "abcdefghijklmnopqrstuvwxyz",
+    "0123456789",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "1qaz2wsx3edc4rfv5tgb6yhn7ujm8ik9ol0p",
+];
+
+/// lowercased with the usual substitutions undone, so `P@ssw0rd` reads as `password`.
+/// maps one char to one char so positions line up with the original
+fn unleet(value: &str) -> Vec<char> {
+    value
+        .chars()
+        .map(|char| match char.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            other => other,
+        })
+        .collect()
+}
+
+fn pool_size(char: char) -> f64 {
+    match char {
+        '0'..='9' => 10.0,
+        'a'..='z' | 'A'..='Z' => 26.0,
+        _ if char.is_ascii() => 33.0,
+        _ => 100.0,
+    }
+}
+
+/// repeats the previous char or continues a sequence from it
+fn follows(previous: char, char: char) -> bool {
+    let (previous, char) = (previous.to_ascii_lowercase(), char.to_ascii_lowercase());
+    let (forward, backward) = (format!("{previous}{char}"), format!("{char}{previous}"));
+
+    previous == char
+        || SEQUENCES
+            .iter()
+            .any(|sequence| sequence.contains(&forward) || sequence.contains(&backward))
+}
+
+/// rough zxcvbn-like score from 0 (guessed instantly) to 4 (not guessable online or
+/// offline). common passwords and the user's own details cost about as much as picking
+/// them from a list, repeats and sequences cost the log of their length, everything
+/// else costs the size of its character class
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> u8 {
+    let chars: Vec<char> = password.chars().collect();
+    let plain = unleet(password);
+    let mut covered = vec![false; chars.len()];
+    let mut bits = 0.0;
+
+    let list_bits = (COMMON_PASSWORDS.len() as f64).log2();
+    let mut words: Vec<(Vec<char>, f64)> = user_inputs
+        .iter()
+        .map(|input| (unleet(input), 1.0))
+        .chain(
+            COMMON_PASSWORDS
+                .iter()
+                .map(|word| (unleet(word), list_bits)),
+        )
+        .filter(|(word, _)| word.len() >= 3)
+        .collect();
+    // longest first, so `password` is not matched as `pass` followed by guesswork
+    words.sort_by_key(|(word, _)| std::cmp::Reverse(word.len()));
+
+    for (word, cost) in &words {
+        let mut start = 0;
+        while start + word.len() <= plain.len() {
+            let end = start + word.len();
+            if plain[start..end] == word[..] && !covered[start..end].contains(&true) {
+                covered[start..end].fill(true);
+                // one more bit when it was capitalized or substituted
+                bits += cost + f64::from(u8::from(chars[start..end] != plain[start..end]));
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+
+    let mut previous = None;
+    let mut run: f64 = 1.0;
+    for (index, &char) in chars.iter().enumerate() {
+        if covered[index] {
+            previous = None;
+            continue;
+        }
+        match previous {
+            Some(previous) if follows(previous, char) => {
+                bits += ((run + 1.0) / run).log2();
+                run += 1.0;
+            }
+            _ => {
+                bits += pool_size(char).log2();
+                run = 1.0;
+            }
+        }
+        previous = Some(char);
+    }
+
+    // zxcvbn's thresholds of 10^3, 10^6, 10^8 and 10^10 guesses
+    match bits {
+        ..10.0 => 0,
+        ..20.0 => 1,
+        ..26.6 => 2,
+        ..33.2 => 3,
+        _ => 4,
+    }
+}
+
+/// SHA-1 hashes of breached passwords, looked up k-anonymity style: only the first five
+/// hex digits of a hash pick the range of suffixes that is compared
+pub enum BreachedPasswords {
+    Disabled,
+    /// one `<PREFIX>.txt` file per prefix with `SUFFIX:COUNT` lines, the layout the
+    /// haveibeenpwned downloader writes, read on demand
+    Directory(PathBuf),
+    /// a single file of `HASH:COUNT` lines loaded at startup, grouped by prefix
+    Memory(HashMap<String, Vec<(String, u64)>>),
+}
+
+fn parse_range_line(line: &str) -> Option<(String, u64)> {
+    let (hash, count) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+    (!hash.is_empty()).then(|| (hash.to_ascii_uppercase(), count.trim().parse().unwrap_or(1)))
+}
+
+impl BreachedPasswords {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if path.is_dir() {
+            return Ok(Self::Directory(path.to_path_buf()));
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("error reading {}: {error}", path.display()))?;
+        let mut ranges: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+        for (hash, count) in contents.lines().filter_map(parse_range_line) {
+            if hash.len() == 40 {
+                let (prefix, suffix) = hash.split_at(5);
+                ranges
+                    .entry(prefix.to_string())
+                    .or_default()
+                    .push((suffix.to_string(), count));
+            }
+        }
+
+        Ok(Self::Memory(ranges))
+    }
+
+    async fn range(&self, prefix: &str) -> Result<Vec<(String, u64)>, GlobalAppError> {
+        match self {
+            Self::Disabled => Ok(Vec::new()),
+            Self::Memory(ranges) => Ok(ranges.get(prefix).cloned().unwrap_or_default()),
+            Self::Directory(directory) => {
+                match tokio::fs::read_to_string(directory.join(format!("{prefix}.txt"))).await {
+                    Ok(contents) => Ok(contents.lines().filter_map(parse_range_line).collect()),
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+                    Err(error) => Err(GlobalAppError::Internal(format!(
+                        "error reading breached password range: {error}"
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// how often the password shows up in breaches, zero when it does not or the check is off
+    pub async fn times_breached(&self, password: &str) -> Result<u64, GlobalAppError> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        Ok(self
+            .range(prefix)
+            .await?
+            .into_iter()
+            .find(|(candidate, _)| candidate == suffix)
+            .map_or(0, |(_, count)| count))
+    }
+}
+
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// argon2 hashes whatever it gets, this keeps that bounded
+    pub max_length: usize,
+    /// 0 to 4, see `estimate_strength`
+    pub min_strength: u8,
+    pub breached: BreachedPasswords,
+}
+
+fn setting<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match dotenvy::var(name) {
+        Ok(value) if !value.is_empty() => value.parse().map_err(|_| format!("invalid {name}")),
+        _ => Ok(default),
+    }
+}
+
+impl PasswordPolicy {
+    /// `PASSWORD_MIN_LENGTH` (8), `PASSWORD_MAX_LENGTH` (128), `PASSWORD_MIN_STRENGTH` (3)
+    /// and `BREACHED_PASSWORDS_PATH`, a file or directory as described on `BreachedPasswords`
+    pub fn from_env() -> Result<Self, String> {
+        let min_strength = setting("PASSWORD_MIN_STRENGTH", 3)?;
+        if min_strength > 4 {
+            return Err("PASSWORD_MIN_STRENGTH must be between 0 and 4".to_string());
+        }
+        let breached = match dotenvy::var("BREACHED_PASSWORDS_PATH") {
+            Ok(path) if !path.is_empty() => BreachedPasswords::load(Path::new(&path))?,
+            _ => BreachedPasswords::Disabled,
+        };
+
+        Ok(Self {
+            min_length: setting("PASSWORD_MIN_LENGTH", 8)?,
+            max_length: setting("PASSWORD_MAX_LENGTH", 128)?,
+            min_strength,
+            breached,
+        })
+    }
+
+    /// every reason the password is refused, reported under `field`. expects the
+    /// normalized username and email of the account
+    pub async fn violations(
+        &self,
+        field: &str,
+        password: &str,
+        username: &str,
+        email: &str,
+    ) -> Result<Vec<FieldError>, GlobalAppError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Ok(vec![FieldError::with_code(
+                field,
+                "too_short",
+                &format!("{field} must be at least {} characters!", self.min_length),
+            )]);
+        }
+        if length > self.max_length {
+            return Ok(vec![FieldError::with_code(
+                field,
+                "too_long",
+                &format!("{field} must be at most {} characters!", self.max_length),
+            )]);
+        }
+
+        let mut errors = Vec::new();
+        let normalized = normalize_identifier(password);
+        let local_part = email.split('@').next().unwrap_or_default();
+
+        // accounts from before usernames were length checked may have very short ones
+        if username.chars().count() >= 3 && normalized.contains(username) {
+            errors.push(FieldError::with_code(
+                field,
+                "contains_username",
+                &format!("{field} must not contain the username!"),
+            ));
+        }
+        if normalized.contains(email)
+            || (local_part.chars().count() >= 3 && normalized.contains(local_part))
+        {
+            errors.push(FieldError::with_code(
+                field,
+                "contains_email",
+                &format!("{field} must not contain the email address!"),
+            ));
+        }
+
+        let strength = estimate_strength(password, &[username, email, local_part]);
+        if strength < self.min_strength {
+            errors.push(FieldError::with_code(
+                field,
+                "too_weak",
+                &format!(
+                    "{field} is too easy to guess (strength {strength} of 4, needs {})! use more words or characters that do not follow a pattern",
+                    self.min_strength
+                ),
+            ));
+        }
+
+        let breaches = self.breached.times_breached(password).await?;
+        if breaches > 0 {
+            errors.push(FieldError::with_code(
+                field,
+                "breached",
+                &format!(
+                    "{field} appeared {breaches} times in known data breaches! choose a different one"
+                ),
+            ));
+        }
+
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(breached: BreachedPasswords) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            min_strength: 3,
+            breached,
+        }
+    }
+
+    async fn codes(policy: &PasswordPolicy, password: &str, username: &str) -> Vec<&'static str> {
+        policy
+            .violations("password", password, username, "jane.doe@example.com")
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|error| error.code)
+            .collect()
+    }
+
+    #[test]
+    fn common_passwords_score_low() {
+        assert!(estimate_strength("P@ssw0rd", &[]) <= 1);
+        assert!(estimate_strength("password123", &[]) <= 1);
+        assert!(estimate_strength("qwertyuiop", &[]) <= 1);
+        assert!(estimate_strength("aaaaaaaaaaaa", &[]) <= 1);
+    }
+
+    #[test]
+    fn long_random_passphrases_score_high() {
+        assert_eq!(estimate_strength("mauve-tractor-gleam-oyster-83", &[]), 4);
+        assert_eq!(estimate_strength("x7#Kq9!vLm2$", &[]), 4);
+    }
+
+    #[test]
+    fn user_inputs_count_as_guessable() {
+        let password = "janedoe2024";
+        assert!(estimate_strength(password, &["janedoe"]) < estimate_strength(password, &[]));
+    }
+
+    #[tokio::test]
+    async fn username_and_email_inclusion_is_reported() {
+        let policy = policy(BreachedPasswords::Disabled);
+
+        assert!(
+            codes(&policy, "Janedoe-gleam-oyster-83", "janedoe")
+                .await
+                .contains(&"contains_username")
+        );
+        assert!(
+            codes(&policy, "mauve-jane.doe-gleam-83", "someone")
+                .await
+                .contains(&"contains_email")
+        );
+        assert!(
+            codes(&policy, "mauve-tractor-gleam-oyster-83", "janedoe")
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn short_legacy_usernames_are_not_matched() {
+        let policy = policy(BreachedPasswords::Disabled);
+
+        assert!(
+            codes(&policy, "mauve-tractor-gleam-oyster-83", "a")
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn length_is_checked_first() {
+        let policy = policy(BreachedPasswords::Disabled);
+
+        assert_eq!(codes(&policy, "short", "janedoe").await, vec!["too_short"]);
+    }
+
+    #[tokio::test]
+    async fn breached_passwords_are_found_in_range_files() {
+        let password = "mauve-tractor-gleam-oyster-83";
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        let directory = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join(format!("{prefix}.txt")),
+            format!("0000000000000000000000000000000000A:3\r\n{suffix}:42\r\n"),
+        )
+        .unwrap();
+        let file = directory.join("all.txt");
+        std::fs::write(&file, format!("{hash}:7\n")).unwrap();
+
+        let by_range = BreachedPasswords::load(&directory).unwrap();
+        let in_memory = BreachedPasswords::load(&file).unwrap();
+        let by_range_result = by_range.times_breached(password).await.unwrap();
+        let in_memory_result = in_memory.times_breached(password).await.unwrap();
+        let unknown = by_range.times_breached("something else").await.unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(by_range_result, 42);
+        assert_eq!(in_memory_result, 7);
+        assert_eq!(unknown, 0);
+        assert!(
+            codes(&policy(in_memory), password, "janedoe")
+                .await
+                .contains(&"breached")
+        );
+    }
+}
@@ -0,0 +1,584 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Cursor, Read, Write},
+};
+
+use chrono::Utc;
+use serde_json::{Map, Value};
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    errors::GlobalAppError,
+    helpers::{currencies::parse_currency, login_attempts::recent_logins},
+    models::{
+        portability::{ArchiveManifest, ArchivedProfile},
+        users::UserProfileDetails,
+    },
+};
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// an uploaded archive may unpack to no more than this, all files together
+const MAX_ARCHIVE_UNPACKED_SIZE: u64 = 48 * 1024 * 1024;
+
+/// a table whose rows belong to a user and travel with their data
+pub struct PortableTable {
+    pub name: &'static str,
+    /// selects the rows of the user bound as $1
+    pub owner: &'static str,
+    /// columns holding ids of rows in tables listed earlier, with the table they point to
+    pub references: &'static [(&'static str, &'static str)],
+    /// two referencing columns that have to stay in ascending order after remapping
+    pub ordered_pair: Option<(&'static str, &'static str)>,
+}
+
+/// in the order they are restored, so every reference points at a table listed before it.
+/// new tables holding user data belong here
+pub const PORTABLE_TABLES: &[PortableTable] = &[
+    PortableTable {
+        name: "categories",
+        owner: "user_id = $1",
+        references: &[],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "accounts",
+        owner: "user_id = $1",
+        references: &[],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "transfers",
+        owner: "user_id = $1",
+        references: &[],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "exchange_rates",
+        owner: "user_id = $1",
+        references: &[],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "budgets",
+        owner: "user_id = $1",
+        references: &[("category_id", "categories")],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "transactions",
+        owner: "user_id = $1",
+        references: &[
+            ("category_id", "categories"),
+            ("account_id", "accounts"),
+            ("transfer_id", "transfers"),
+        ],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "recurring_transactions",
+        owner: "user_id = $1",
+        references: &[("category_id", "categories"), ("account_id", "accounts")],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "recurring_occurrences",
+        owner: "recurring_transaction_id IN (SELECT id FROM recurring_transactions WHERE user_id = $1)",
+        references: &[
+            ("recurring_transaction_id", "recurring_transactions"),
+            ("transaction_id", "transactions"),
+        ],
+        ordered_pair: None,
+    },
+    PortableTable {
+        name: "duplicate_dismissals",
+        owner: "user_id = $1",
+        references: &[
+            ("transaction_id", "transactions"),
+            ("duplicate_id", "transactions"),
+        ],
+        ordered_pair: Some(("transaction_id", "duplicate_id")),
+    },
+];
+
+async fn table_columns(
+    conn: &mut PgConnection,
+    table: &str,
+) -> Result<Vec<String>, GlobalAppError> {
+    Ok(query_as::<_, (String,)>(
+        r#"SELECT column_name FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = $1
+        ORDER BY ordinal_position"#,
+    )
+    .bind(table)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|(column,)| column)
+    .collect())
+}
+
+/// every row of the user as a json object without user_id. numbers are kept as strings
+/// so amounts and rates come back exactly as they were stored
+async fn table_rows(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    table: &PortableTable,
+) -> Result<Vec<Value>, GlobalAppError> {
+    let (rows,) = query_as::<_, (String,)>(&format!(
+        r#"SELECT COALESCE(jsonb_agg((
+            SELECT jsonb_object_agg(
+                key,
+                CASE WHEN jsonb_typeof(value) = 'number' THEN to_jsonb(value #>> '{{}}') ELSE value END
+            )
+            FROM jsonb_each(to_jsonb(t) - 'user_id')
+        )), '[]')::text
+        FROM {} t
+        WHERE {}"#,
+        table.name, table.owner
+    ))
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    serde_json::from_str(&rows)
+        .map_err(|_| GlobalAppError::Internal("error reading exported rows!".to_string()))
+}
+
+fn csv_field(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn to_csv(columns: &[String], rows: &[Value]) -> Result<Vec<u8>, GlobalAppError> {
+    let csv_error = |_| GlobalAppError::Internal("error writing csv!".to_string());
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(columns).map_err(csv_error)?;
+    for row in rows {
+        writer
+            .write_record(columns.iter().map(|column| csv_field(row.get(column))))
+            .map_err(csv_error)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|_| GlobalAppError::Internal("error writing csv!".to_string()))
+}
+
+/// zip archive of the profile and every portable table, each table as json for
+/// `import_archive` and as csv for spreadsheets
+pub async fn export_archive(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Vec<u8>, GlobalAppError> {
+    let mut profile = query_as::<_, UserProfileDetails>(
+        "SELECT id, name, email, created_at, updated_at, is_active, email_verified_at, totp_enabled_at IS NOT NULL AS two_factor_enabled, base_currency FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    profile.recent_logins = recent_logins(&mut *conn, user_id).await?;
+
+    let mut files = vec![(
+        "profile.json".to_string(),
+        serde_json::to_vec_pretty(&profile).unwrap_or_default(),
+    )];
+    for table in PORTABLE_TABLES {
+        let columns: Vec<String> = table_columns(&mut *conn, table.name)
+            .await?
+            .into_iter()
+            .filter(|column| column != "user_id")
+            .collect();
+        let rows = table_rows(&mut *conn, user_id, table).await?;
+
+        files.push((format!("{}.csv", table.name), to_csv(&columns, &rows)?));
+        files.push((
+            format!("{}.json", table.name),
+            serde_json::to_vec_pretty(&rows).unwrap_or_default(),
+        ));
+    }
+
+    let manifest = ArchiveManifest {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        tables: PORTABLE_TABLES
+            .iter()
+            .map(|table| table.name.to_string())
+            .collect(),
+    };
+    files.insert(
+        0,
+        (
+            "manifest.json".to_string(),
+            serde_json::to_vec_pretty(&manifest).unwrap_or_default(),
+        ),
+    );
+
+    let zip_error = |_| GlobalAppError::Internal("error writing archive!".to_string());
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, contents) in files {
+        zip.start_file(name, options).map_err(zip_error)?;
+        zip.write_all(&contents)
+            .map_err(|_| GlobalAppError::Internal("error writing archive!".to_string()))?;
+    }
+
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+fn invalid_archive(message: &str) -> GlobalAppError {
+    GlobalAppError::validation("file", message)
+}
+
+fn archive_too_large() -> GlobalAppError {
+    invalid_archive(&format!(
+        "archive unpacks to more than {} MiB!",
+        MAX_ARCHIVE_UNPACKED_SIZE / (1024 * 1024)
+    ))
+}
+
+/// the sizes the archive declares are checked up front, `read_archive_file` still counts
+/// what actually comes out in case they lie
+fn ensure_unpacked_size(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<(), GlobalAppError> {
+    let mut total: u64 = 0;
+    for index in 0..archive.len() {
+        let file = archive
+            .by_index_raw(index)
+            .map_err(|_| invalid_archive("file is not a valid zip archive!"))?;
+        total = total.saturating_add(file.size());
+    }
+
+    if total > MAX_ARCHIVE_UNPACKED_SIZE {
+        return Err(archive_too_large());
+    }
+
+    Ok(())
+}
+
+/// reads and parses one file, taking what it unpacks to from `budget`
+fn read_archive_file<T: serde::de::DeserializeOwned>(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+    budget: &mut u64,
+) -> Result<Option<T>, GlobalAppError> {
+    let file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(_) => return Err(invalid_archive("file is not a valid zip archive!")),
+    };
+
+    let mut contents = Vec::new();
+    file.take(*budget + 1)
+        .read_to_end(&mut contents)
+        .map_err(|_| invalid_archive(&format!("{name} could not be read!")))?;
+    *budget = budget
+        .checked_sub(contents.len() as u64)
+        .ok_or_else(archive_too_large)?;
+
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|error| invalid_archive(&format!("{name} is not valid: {error}!")))
+}
+
+/// gives every row a new id and points its references at the new ids of the rows they
+/// referenced in the archive
+fn remap_rows(
+    table: &PortableTable,
+    rows: Vec<Value>,
+    columns: &[String],
+    user_id: Uuid,
+    new_ids: &mut HashMap<&'static str, HashMap<String, String>>,
+) -> Result<Vec<Value>, GlobalAppError> {
+    let has_id = columns.iter().any(|column| column == "id");
+    let has_user_id = columns.iter().any(|column| column == "user_id");
+    let mut remapped = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let Value::Object(row) = row else {
+            return Err(invalid_archive(&format!(
+                "{}.json must be a list of objects!",
+                table.name
+            )));
+        };
+        // columns this version does not know about are dropped
+        let mut row: Map<String, Value> = row
+            .into_iter()
+            .filter(|(column, _)| columns.contains(column) && column != "user_id")
+            .collect();
+
+        if has_id {
+            let Some(Value::String(old_id)) = row.get("id") else {
+                return Err(invalid_archive(&format!(
+                    "every row of {}.json needs an id!",
+                    table.name
+                )));
+            };
+            let new_id = Uuid::new_v4().to_string();
+            new_ids
+                .entry(table.name)
+                .or_default()
+                .insert(old_id.clone(), new_id.clone());
+            row.insert("id".to_string(), Value::String(new_id));
+        }
+        if has_user_id {
+            row.insert("user_id".to_string(), Value::String(user_id.to_string()));
+        }
+
+        for (column, referenced) in table.references {
+            let Some(Value::String(old_id)) = row.get(*column) else {
+                continue;
+            };
+            let new_id = new_ids
+                .get(referenced)
+                .and_then(|ids| ids.get(old_id))
+                .ok_or_else(|| {
+                    invalid_archive(&format!(
+                        "{}.json references {old_id} which is not in {referenced}.json!",
+                        table.name
+                    ))
+                })?;
+            row.insert(column.to_string(), Value::String(new_id.clone()));
+        }
+
+        if let Some((first, second)) = table.ordered_pair
+            && let (Some(Value::String(a)), Some(Value::String(b))) =
+                (row.get(first), row.get(second))
+            && Uuid::parse_str(a).ok() > Uuid::parse_str(b).ok()
+        {
+            let (a, b) = (a.clone(), b.clone());
+            row.insert(first.to_string(), Value::String(b));
+            row.insert(second.to_string(), Value::String(a));
+        }
+
+        remapped.push(Value::Object(row));
+    }
+
+    Ok(remapped)
+}
+
+/// restores an archive made by `export_archive` into the account of `user_id`, which has
+/// to be empty. expects to run inside a transaction
+pub async fn import_archive(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    data: &[u8],
+) -> Result<BTreeMap<String, usize>, GlobalAppError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|_| invalid_archive("file is not a valid zip archive!"))?;
+    ensure_unpacked_size(&mut archive)?;
+    let mut budget = MAX_ARCHIVE_UNPACKED_SIZE;
+
+    let manifest =
+        read_archive_file::<ArchiveManifest>(&mut archive, "manifest.json", &mut budget)?
+            .ok_or_else(|| invalid_archive("manifest.json is missing!"))?;
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(invalid_archive(&format!(
+            "archive version {} is newer than the supported version {ARCHIVE_VERSION}!",
+            manifest.version
+        )));
+    }
+
+    for table in PORTABLE_TABLES {
+        let (has_rows,) = query_as::<_, (bool,)>(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE {})",
+            table.name, table.owner
+        ))
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if has_rows {
+            return Err(GlobalAppError::Conflict(
+                "archives can only be imported into an account without any data!".to_string(),
+            ));
+        }
+    }
+
+    if let Some(profile) =
+        read_archive_file::<ArchivedProfile>(&mut archive, "profile.json", &mut budget)?
+    {
+        query("UPDATE users SET base_currency = $1 WHERE id = $2")
+            .bind(parse_currency("base_currency", &profile.base_currency)?)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let mut new_ids = HashMap::new();
+    let mut imported = BTreeMap::new();
+    for table in PORTABLE_TABLES {
+        let rows = read_archive_file::<Vec<Value>>(
+            &mut archive,
+            &format!("{}.json", table.name),
+            &mut budget,
+        )?
+        .unwrap_or_default();
+        let columns = table_columns(&mut *conn, table.name).await?;
+        let rows = remap_rows(table, rows, &columns, user_id, &mut new_ids)?;
+        imported.insert(table.name.to_string(), rows.len());
+        if rows.is_empty() {
+            continue;
+        }
+
+        // only columns the archive has values for, the others keep their defaults
+        let present: Vec<String> = columns
+            .iter()
+            .filter(|column| rows[0].get(column.as_str()).is_some())
+            .map(|column| format!(r#""{column}""#))
+            .collect();
+        let present = present.join(", ");
+
+        query(&format!(
+            "INSERT INTO {table} ({present}) SELECT {present} FROM jsonb_populate_recordset(NULL::{table}, $1::jsonb)",
+            table = table.name
+        ))
+        .bind(Value::Array(rows).to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|error| match error {
+            // the database's message names constraints and columns, it only goes to the log
+            sqlx::Error::Database(error) => {
+                eprintln!("restoring {} failed: {}", table.name, error.message());
+                invalid_archive(&format!(
+                    "{}.json has rows that are incomplete or do not fit together!",
+                    table.name
+                ))
+            }
+            error => error.into(),
+        })?;
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn table(name: &str) -> &'static PortableTable {
+        PORTABLE_TABLES
+            .iter()
+            .find(|table| table.name == name)
+            .unwrap()
+    }
+
+    fn columns(columns: &[&str]) -> Vec<String> {
+        columns.iter().map(|column| column.to_string()).collect()
+    }
+
+    #[test]
+    fn rows_get_new_ids_and_the_importing_user() {
+        let user_id = Uuid::new_v4();
+        let mut new_ids = HashMap::new();
+        let rows = remap_rows(
+            table("categories"),
+            vec![json!({ "id": "old", "user_id": "someone", "name": "food", "legacy": 1 })],
+            &columns(&["id", "user_id", "name"]),
+            user_id,
+            &mut new_ids,
+        )
+        .unwrap();
+
+        let new_id = &new_ids["categories"]["old"];
+        assert_eq!(
+            rows,
+            vec![json!({ "id": new_id, "user_id": user_id.to_string(), "name": "food" })]
+        );
+    }
+
+    #[test]
+    fn references_follow_the_new_ids() {
+        let mut new_ids = HashMap::from([(
+            "categories",
+            HashMap::from([("old".to_string(), "new".to_string())]),
+        )]);
+        let rows = remap_rows(
+            table("budgets"),
+            vec![json!({ "id": "budget", "category_id": "old" })],
+            &columns(&["id", "category_id"]),
+            Uuid::new_v4(),
+            &mut new_ids,
+        )
+        .unwrap();
+
+        assert_eq!(rows[0]["category_id"], "new");
+    }
+
+    #[test]
+    fn missing_references_are_rejected() {
+        let result = remap_rows(
+            table("budgets"),
+            vec![json!({ "id": "budget", "category_id": "unknown" })],
+            &columns(&["id", "category_id"]),
+            Uuid::new_v4(),
+            &mut HashMap::new(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rows_must_be_objects_with_an_id() {
+        let columns = columns(&["id", "name"]);
+
+        assert!(
+            remap_rows(
+                table("categories"),
+                vec![json!(["id"])],
+                &columns,
+                Uuid::new_v4(),
+                &mut HashMap::new()
+            )
+            .is_err()
+        );
+        assert!(
+            remap_rows(
+                table("categories"),
+                vec![json!({ "name": "food" })],
+                &columns,
+                Uuid::new_v4(),
+                &mut HashMap::new()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn ordered_pairs_are_swapped_when_the_new_ids_are_out_of_order() {
+        let (low, high) = {
+            let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+            (a.min(b).to_string(), a.max(b).to_string())
+        };
+        let mut new_ids = HashMap::from([(
+            "transactions",
+            HashMap::from([
+                ("first".to_string(), high.clone()),
+                ("second".to_string(), low.clone()),
+            ]),
+        )]);
+        let rows = remap_rows(
+            table("duplicate_dismissals"),
+            vec![
+                json!({ "transaction_id": "first", "duplicate_id": "second" }),
+                json!({ "transaction_id": "second", "duplicate_id": "first" }),
+            ],
+            &columns(&["user_id", "transaction_id", "duplicate_id"]),
+            Uuid::new_v4(),
+            &mut new_ids,
+        )
+        .unwrap();
+
+        for row in rows {
+            assert_eq!(row["transaction_id"], low.as_str());
+            assert_eq!(row["duplicate_id"], high.as_str());
+        }
+    }
+}
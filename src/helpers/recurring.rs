@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Months, Utc};
+use sqlx::{PgConnection, PgPool, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    models::recurring::{
+        OccurrenceStatus, RecurrenceFrequency, RecurrenceRule, RecurringTransactionInfo,
+    },
+};
+
+pub const RECURRING_INFO_SELECT: &str = r#"SELECT
+        r.id AS recurring_transaction_id,
+        r.amount,
+        r.currency,
+        r.description,
+        r.account_id,
+        r.frequency,
+        r.repeat_every,
+        r.day_of_month,
+        r.starts_at,
+        r.ends_at,
+        r.occurrence_limit,
+        r.paused,
+        r.next_occurrence,
+        r.next_due_at,
+        r.updated_at,
+        c.id,
+        c.name,
+        c.type,
+        c.is_savings,
+        c.created_at
+        FROM recurring_transactions r
+        INNER JOIN categories c
+        ON r.category_id = c.id"#;
+
+/// the most occurrences a single catch up goes through, a series that is further behind
+/// is picked up again by the next run of the scheduler
+pub const MAX_CATCH_UP_OCCURRENCES: i32 = 366;
+
+/// `day` of the month `first` falls in, clamped to the last day of that month
+fn on_day_of_month(first: DateTime<Utc>, day: u32) -> Option<DateTime<Utc>> {
+    let days_in_month = (first.checked_add_months(Months::new(1))? - first).num_days() as u32;
+    first.checked_add_days(Days::new(u64::from(day.min(days_in_month) - 1)))
+}
+
+/// when the given occurrence is scheduled, None when the series is over by then
+pub fn occurrence_at(rule: &RecurrenceRule, occurrence: i32) -> Option<DateTime<Utc>> {
+    if occurrence < 0 || rule.count.is_some_and(|count| occurrence >= count) {
+        return None;
+    }
+    let steps = u32::try_from(rule.interval.checked_mul(occurrence)?).ok()?;
+    let start = rule.starts_at;
+
+    let scheduled_for = match rule.frequency {
+        RecurrenceFrequency::Daily => start.checked_add_days(Days::new(u64::from(steps)))?,
+        RecurrenceFrequency::Weekly => start.checked_add_days(Days::new(u64::from(steps) * 7))?,
+        RecurrenceFrequency::Monthly => {
+            let day = rule
+                .day_of_month
+                .map(|day| day as u32)
+                .unwrap_or_else(|| start.day());
+            let first = start.checked_sub_days(Days::new(u64::from(start.day() - 1)))?;
+            // a day of month earlier than starts_at pushes the first occurrence a month out
+            let offset = u32::from(on_day_of_month(first, day)? < start);
+            on_day_of_month(
+                first.checked_add_months(Months::new(steps.checked_add(offset)?))?,
+                day,
+            )?
+        }
+        // every occurrence is computed from the start so a 29th of february only
+        // falls back to the 28th in the years that need it
+        RecurrenceFrequency::Yearly => {
+            start.checked_add_months(Months::new(steps.checked_mul(12)?))?
+        }
+    };
+
+    rule.ends_at
+        .is_none_or(|ends_at| scheduled_for <= ends_at)
+        .then_some(scheduled_for)
+}
+
+/// stores how far the scheduler got, expects the series row to be locked
+pub async fn save_progress(
+    conn: &mut PgConnection,
+    series_id: Uuid,
+    rule: &RecurrenceRule,
+    next_occurrence: i32,
+) -> Result<(), GlobalAppError> {
+    query("UPDATE recurring_transactions SET next_occurrence = $1, next_due_at = $2 WHERE id = $3")
+        .bind(next_occurrence)
+        .bind(occurrence_at(rule, next_occurrence))
+        .bind(series_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// handles the occurrences due by `now` with the given status, up to
+/// `MAX_CATCH_UP_OCCURRENCES` of them, occurrences that were skipped ahead of time stay
+/// skipped. skipping goes through all that are due but only keeps the latest on record.
+/// expects the series row to be locked
+pub async fn catch_up(
+    conn: &mut PgConnection,
+    series: &RecurringTransactionInfo,
+    now: DateTime<Utc>,
+    status: OccurrenceStatus,
+) -> Result<u64, GlobalAppError> {
+    let mut occurrence = series.next_occurrence;
+    let mut handled = 0;
+
+    if status == OccurrenceStatus::Skipped {
+        let mut not_due = occurrence;
+        while occurrence_at(&series.rule, not_due).is_some_and(|at| at <= now) {
+            not_due += 1;
+        }
+        occurrence = occurrence.max(not_due - MAX_CATCH_UP_OCCURRENCES);
+    }
+    let until = occurrence.saturating_add(MAX_CATCH_UP_OCCURRENCES);
+
+    while occurrence < until
+        && let Some(scheduled_for) = occurrence_at(&series.rule, occurrence)
+        && scheduled_for <= now
+    {
+        // the primary key makes a second attempt at the same occurrence a no-op
+        let inserted = query(
+            r#"INSERT INTO recurring_occurrences (recurring_transaction_id, occurrence, scheduled_for, status)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING"#,
+        )
+        .bind(series.id)
+        .bind(occurrence)
+        .bind(scheduled_for)
+        .bind(status)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected()
+            == 1;
+
+        if inserted {
+            handled += 1;
+        }
+        if inserted && status == OccurrenceStatus::Posted {
+            query(
+                r#"WITH posted AS (
+                    INSERT INTO transactions (user_id, category_id, account_id, description, amount, currency, transaction_date)
+                    SELECT user_id, category_id, account_id, description, amount, currency, $3
+                    FROM recurring_transactions
+                    WHERE id = $1
+                    RETURNING id
+                )
+                UPDATE recurring_occurrences
+                SET transaction_id = (SELECT id FROM posted)
+                WHERE recurring_transaction_id = $1 AND occurrence = $2"#,
+            )
+            .bind(series.id)
+            .bind(occurrence)
+            .bind(scheduled_for)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        occurrence += 1;
+    }
+
+    save_progress(conn, series.id, &series.rule, occurrence).await?;
+
+    Ok(handled)
+}
+
+async fn post_series(
+    pool: &PgPool,
+    series_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<u64, GlobalAppError> {
+    let mut tx = pool.begin().await?;
+
+    // another instance may be working through the same series, leave it to them
+    let Some(series) = query_as::<_, RecurringTransactionInfo>(&format!(
+        "{RECURRING_INFO_SELECT} WHERE r.id = $1 AND NOT r.paused FOR UPDATE OF r SKIP LOCKED"
+    ))
+    .bind(series_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(0);
+    };
+
+    let posted = catch_up(&mut tx, &series, now, OccurrenceStatus::Posted).await?;
+
+    tx.commit().await?;
+
+    Ok(posted)
+}
+
+/// posts every due occurrence of every active series, each series in its own
+/// transaction so one failure does not hold the others back
+pub async fn post_due_occurrences(pool: &PgPool) -> Result<u64, GlobalAppError> {
+    let now = Utc::now();
+    let due = query_as::<_, (Uuid,)>(
+        "SELECT id FROM recurring_transactions WHERE NOT paused AND next_due_at <= $1",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    let mut posted = 0;
+    for (series_id,) in due {
+        match post_series(pool, series_id, now).await {
+            Ok(count) => posted += count,
+            Err(error) => eprintln!(
+                "recurring transaction {series_id} failed: {}",
+                error.message()
+            ),
+        }
+    }
+
+    Ok(posted)
+}
+
+/// runs forever, meant to be spawned once at startup
+pub async fn run_recurring_scheduler(pool: PgPool, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+
+    loop {
+        ticker.tick().await;
+        if let Err(error) = post_due_occurrences(&pool).await {
+            eprintln!("recurring scheduler failed: {}", error.message());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, 9, 30, 0).unwrap()
+    }
+
+    fn rule(frequency: RecurrenceFrequency, starts_at: DateTime<Utc>) -> RecurrenceRule {
+        RecurrenceRule {
+            frequency,
+            interval: 1,
+            day_of_month: None,
+            starts_at,
+            ends_at: None,
+            count: None,
+        }
+    }
+
+    fn occurrences(rule: &RecurrenceRule, count: i32) -> Vec<Option<DateTime<Utc>>> {
+        (0..count)
+            .map(|occurrence| occurrence_at(rule, occurrence))
+            .collect()
+    }
+
+    #[test]
+    fn daily_and_weekly_series_step_by_their_interval() {
+        let mut daily = rule(RecurrenceFrequency::Daily, date(2024, 2, 27));
+        daily.interval = 2;
+        assert_eq!(
+            occurrences(&daily, 3),
+            [date(2024, 2, 27), date(2024, 2, 29), date(2024, 3, 2)].map(Some)
+        );
+
+        let weekly = rule(RecurrenceFrequency::Weekly, date(2024, 12, 25));
+        assert_eq!(occurrence_at(&weekly, 1), Some(date(2025, 1, 1)));
+    }
+
+    #[test]
+    fn monthly_series_clamp_to_the_end_of_short_months() {
+        let monthly = rule(RecurrenceFrequency::Monthly, date(2023, 12, 31));
+
+        assert_eq!(
+            occurrences(&monthly, 4),
+            [
+                date(2023, 12, 31),
+                date(2024, 1, 31),
+                date(2024, 2, 29),
+                date(2024, 3, 31)
+            ]
+            .map(Some)
+        );
+    }
+
+    #[test]
+    fn an_earlier_day_of_month_starts_the_following_month() {
+        let mut monthly = rule(RecurrenceFrequency::Monthly, date(2024, 1, 20));
+        monthly.day_of_month = Some(5);
+        assert_eq!(
+            occurrences(&monthly, 2),
+            [date(2024, 2, 5), date(2024, 3, 5)].map(Some)
+        );
+
+        monthly.day_of_month = Some(30);
+        assert_eq!(
+            occurrences(&monthly, 2),
+            [date(2024, 1, 30), date(2024, 2, 29)].map(Some)
+        );
+    }
+
+    #[test]
+    fn yearly_series_from_a_leap_day_only_fall_back_when_needed() {
+        let yearly = rule(RecurrenceFrequency::Yearly, date(2024, 2, 29));
+
+        assert_eq!(
+            occurrences(&yearly, 5),
+            [
+                date(2024, 2, 29),
+                date(2025, 2, 28),
+                date(2026, 2, 28),
+                date(2027, 2, 28),
+                date(2028, 2, 29)
+            ]
+            .map(Some)
+        );
+    }
+
+    #[test]
+    fn series_end_by_count_or_date() {
+        let mut daily = rule(RecurrenceFrequency::Daily, date(2024, 1, 1));
+        daily.count = Some(2);
+        assert_eq!(occurrence_at(&daily, 1), Some(date(2024, 1, 2)));
+        assert_eq!(occurrence_at(&daily, 2), None);
+        assert_eq!(occurrence_at(&daily, -1), None);
+
+        daily.count = None;
+        daily.ends_at = Some(date(2024, 1, 3));
+        assert_eq!(occurrence_at(&daily, 2), Some(date(2024, 1, 3)));
+        assert_eq!(occurrence_at(&daily, 3), None);
+    }
+}
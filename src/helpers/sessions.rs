@@ -0,0 +1,191 @@
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    models::sessions::{SessionRow, TokenRevocationRow},
+};
+
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+pub fn generate_refresh_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// starts a new session family for the user and returns its first refresh token
+pub async fn create_session(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<(Uuid, String), GlobalAppError> {
+    let session_id = query_as::<_, (Uuid,)>(
+        "INSERT INTO sessions (user_id, expires_at) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS))
+    .fetch_one(&mut *conn)
+    .await?
+    .0;
+
+    let refresh_token = issue_refresh_token(conn, session_id).await?;
+
+    Ok((session_id, refresh_token))
+}
+
+async fn issue_refresh_token(
+    conn: &mut PgConnection,
+    session_id: Uuid,
+) -> Result<String, GlobalAppError> {
+    let refresh_token = generate_refresh_token();
+
+    query("INSERT INTO refresh_tokens (session_id, token_hash) VALUES ($1, $2)")
+        .bind(session_id)
+        .bind(hash_refresh_token(&refresh_token))
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(refresh_token)
+}
+
+/// consumes a refresh token and hands out its successor within the same session family.
+/// presenting a token that was already rotated revokes the whole family, so the caller
+/// must commit even when `None` is returned.
+pub async fn rotate_refresh_token(
+    conn: &mut PgConnection,
+    refresh_token: &str,
+) -> Result<Option<(SessionRow, String)>, GlobalAppError> {
+    let token_hash = hash_refresh_token(refresh_token);
+
+    let consumed = query_as::<_, SessionRow>(
+        r#"UPDATE refresh_tokens rt
+        SET used_at = NOW()
+        FROM sessions s
+        WHERE rt.session_id = s.id
+        AND rt.token_hash = $1
+        AND rt.used_at IS NULL
+        RETURNING s.id, s.user_id, s.expires_at, s.revoked_at"#,
+    )
+    .bind(&token_hash)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(session) = consumed else {
+        // either the token never existed or it has already been rotated, in which case
+        // somebody is replaying it and the whole family can no longer be trusted
+        query(
+            r#"UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE revoked_at IS NULL
+            AND id = (SELECT session_id FROM refresh_tokens WHERE token_hash = $1)"#,
+        )
+        .bind(&token_hash)
+        .execute(&mut *conn)
+        .await?;
+
+        return Ok(None);
+    };
+
+    if session.revoked_at.is_some() || session.expires_at <= Utc::now() {
+        return Ok(None);
+    }
+
+    let new_refresh_token = issue_refresh_token(conn, session.id).await?;
+
+    Ok(Some((session, new_refresh_token)))
+}
+
+pub async fn revoke_session(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<(), GlobalAppError> {
+    query("UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await
+        ?;
+
+    Ok(())
+}
+
+/// kills every session of the user and moves the "tokens issued before" watermark to now,
+/// so all access tokens handed out so far stop being accepted by `validate_jwt`
+pub async fn revoke_all_sessions(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<(), GlobalAppError> {
+    query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    // the app's clock, the same one that stamps `iat_micros`, so skew between the app
+    // and database hosts cannot move tokens to the wrong side of the watermark
+    query("UPDATE users SET tokens_valid_after = $1 WHERE id = $2")
+        .bind(Utc::now())
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// puts a single access token on the denylist until it would have expired anyway
+pub async fn revoke_access_token(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    jti: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<(), GlobalAppError> {
+    query("DELETE FROM revoked_tokens WHERE expires_at < NOW()")
+        .execute(&mut *conn)
+        .await?;
+
+    query("INSERT INTO revoked_tokens (jti, user_id, expires_at) VALUES ($1, $2, $3) ON CONFLICT (jti) DO NOTHING")
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&mut *conn)
+        .await
+        ?;
+
+    Ok(())
+}
+
+/// checks the token against the per-token denylist and the per-user watermark.
+/// a token whose user no longer exists counts as revoked.
+pub async fn is_access_token_revoked(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    jti: Uuid,
+    issued_at: DateTime<Utc>,
+) -> Result<bool, GlobalAppError> {
+    let row = query_as::<_, TokenRevocationRow>(
+        r#"SELECT
+        u.tokens_valid_after,
+        EXISTS (SELECT 1 FROM revoked_tokens r WHERE r.jti = $2) AS is_denied
+        FROM users u
+        WHERE u.id = $1"#,
+    )
+    .bind(user_id)
+    .bind(jti)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(true);
+    };
+
+    // older tokens without microseconds are refused for the whole second of the watermark
+    let before_watermark = row
+        .tokens_valid_after
+        .is_some_and(|valid_after| issued_at < valid_after);
+
+    Ok(row.is_denied || before_watermark)
+}
@@ -0,0 +1,340 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use slug::slugify;
+use sqlx::{PgConnection, Postgres, QueryBuilder, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::{FieldError, GlobalAppError},
+    helpers::{
+        accounts::ensure_account_owned,
+        categories::category_id_by_slug,
+        currencies::{normalize_currency, parse_currency},
+    },
+    models::transactions::{
+        SortOrder, TransactionCursor, TransactionFilters, TransactionInfo, TransactionRequest,
+        TransactionSort,
+    },
+};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+pub const DEFAULT_DUPLICATE_WINDOW_DAYS: i32 = 3;
+pub const MAX_DUPLICATE_WINDOW_DAYS: i32 = 31;
+
+/// pairs every transaction `a` with the transactions `b` that look like the same purchase:
+/// same category, amount and currency, dated at most $2 days apart and with descriptions
+/// that are similar or missing on either side. pairs the user dismissed are left out,
+/// expects $1 to be the user id
+pub const DUPLICATE_JOIN: &str = r#"INNER JOIN transactions b
+        ON b.user_id = $1
+        AND b.id <> a.id
+        AND b.category_id = a.category_id
+        AND b.amount = a.amount
+        AND b.currency = a.currency
+        AND b.transaction_date BETWEEN a.transaction_date - make_interval(days => $2)
+            AND a.transaction_date + make_interval(days => $2)
+        AND (
+            COALESCE(a.description, '') = ''
+            OR COALESCE(b.description, '') = ''
+            OR similarity(lower(a.description), lower(b.description)) >= 0.4
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM duplicate_dismissals d
+            WHERE d.transaction_id = LEAST(a.id, b.id)
+            AND d.duplicate_id = GREATEST(a.id, b.id)
+        )"#;
+
+pub fn duplicate_window(window_days: Option<i32>) -> Result<i32, GlobalAppError> {
+    let window_days = window_days.unwrap_or(DEFAULT_DUPLICATE_WINDOW_DAYS);
+    if !(0..=MAX_DUPLICATE_WINDOW_DAYS).contains(&window_days) {
+        return Err(GlobalAppError::validation(
+            "window_days",
+            &format!("window_days must be between 0 and {MAX_DUPLICATE_WINDOW_DAYS}!"),
+        ));
+    }
+
+    Ok(window_days)
+}
+
+/// transactions stored by earlier requests that look like the same one as `transaction_id`,
+/// rows written in the current database transaction never count so a preview or a batch
+/// does not point at itself
+pub async fn possible_duplicates(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: Uuid,
+    window_days: i32,
+) -> Result<Vec<Uuid>, GlobalAppError> {
+    let duplicates = query_as::<_, (Uuid,)>(&format!(
+        r#"SELECT b.id FROM transactions a
+        {DUPLICATE_JOIN}
+        WHERE a.id = $3 AND a.user_id = $1 AND b.created_at < NOW()
+        ORDER BY b.transaction_date, b.id"#
+    ))
+    .bind(user_id)
+    .bind(window_days)
+    .bind(transaction_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(duplicates.into_iter().map(|(id,)| id).collect())
+}
+
+/// validates and stores one transaction, shared by every way transactions come in.
+/// `import_id` is only set for transactions that came from a statement import
+pub async fn insert_transaction(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction: &TransactionRequest,
+    import_id: Option<&str>,
+) -> Result<Uuid, GlobalAppError> {
+    let category_id = category_id_by_slug(&mut *conn, user_id, &transaction.category).await?;
+    if let Some(account_id) = transaction.account_id {
+        ensure_account_owned(&mut *conn, user_id, account_id, "account_id").await?;
+    }
+    let currency = transaction
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let (transaction_id,) = query_as::<_, (Uuid,)>(
+        r#"INSERT INTO transactions (user_id, category_id, description, amount, transaction_date, account_id, currency, import_id)
+        VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, (SELECT base_currency FROM users WHERE id = $1)), $8)
+        RETURNING id"#,
+    )
+    .bind(user_id)
+    .bind(category_id)
+    .bind(&transaction.description)
+    .bind(transaction.amount)
+    .bind(transaction.transaction_date)
+    .bind(transaction.account_id)
+    .bind(currency)
+    .bind(import_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(transaction_id)
+}
+
+fn split_list(value: &Option<String>) -> Vec<&str> {
+    value
+        .as_deref()
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// appends `AND ...` clauses for the given filters, expects the query to alias
+/// transactions as `t` and categories as `c` and to already have a `WHERE`
+pub fn push_transaction_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    filters: &TransactionFilters,
+) -> Result<(), GlobalAppError> {
+    let mut errors = Vec::new();
+
+    if let (Some(from), Some(to)) = (filters.from, filters.to)
+        && from > to
+    {
+        errors.push(FieldError::new("from", "from must not be after to!"));
+    }
+    if let (Some(min), Some(max)) = (filters.min_amount, filters.max_amount)
+        && min > max
+    {
+        errors.push(FieldError::new(
+            "min_amount",
+            "min_amount must not be greater than max_amount!",
+        ));
+    }
+
+    let mut category_ids = Vec::new();
+    for id in split_list(&filters.category_ids) {
+        match Uuid::parse_str(id) {
+            Ok(id) => category_ids.push(id),
+            Err(_) => errors.push(FieldError::new(
+                "category_ids",
+                &format!("'{id}' is not a valid uuid!"),
+            )),
+        }
+    }
+
+    let mut account_ids = Vec::new();
+    for id in split_list(&filters.account_ids) {
+        match Uuid::parse_str(id) {
+            Ok(id) => account_ids.push(id),
+            Err(_) => errors.push(FieldError::new(
+                "account_ids",
+                &format!("'{id}' is not a valid uuid!"),
+            )),
+        }
+    }
+
+    let mut currencies = Vec::new();
+    for code in split_list(&filters.currencies) {
+        match normalize_currency(code) {
+            Some(code) => currencies.push(code),
+            None => errors.push(FieldError::new(
+                "currencies",
+                &format!("'{code}' is not an ISO 4217 currency!"),
+            )),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(GlobalAppError::Validation(errors));
+    }
+
+    if let Some(from) = filters.from {
+        builder.push(" AND t.transaction_date >= ").push_bind(from);
+    }
+    if let Some(to) = filters.to {
+        builder.push(" AND t.transaction_date <= ").push_bind(to);
+    }
+    if !category_ids.is_empty() {
+        builder
+            .push(" AND c.id = ANY(")
+            .push_bind(category_ids)
+            .push(")");
+    }
+    if !account_ids.is_empty() {
+        builder
+            .push(" AND t.account_id = ANY(")
+            .push_bind(account_ids)
+            .push(")");
+    }
+    if !currencies.is_empty() {
+        builder
+            .push(" AND t.currency = ANY(")
+            .push_bind(currencies)
+            .push(")");
+    }
+    let slugs: Vec<String> = split_list(&filters.categories)
+        .into_iter()
+        .map(slugify)
+        .collect();
+    if !slugs.is_empty() {
+        builder
+            .push(" AND c.slug = ANY(")
+            .push_bind(slugs)
+            .push(")");
+    }
+    if let Some(category_type) = filters.category_type {
+        builder.push(" AND c.type = ").push_bind(category_type);
+    }
+    if let Some(is_savings) = filters.is_savings {
+        builder.push(" AND c.is_savings = ").push_bind(is_savings);
+    }
+    if let Some(min_amount) = filters.min_amount {
+        builder.push(" AND t.amount >= ").push_bind(min_amount);
+    }
+    if let Some(max_amount) = filters.max_amount {
+        builder.push(" AND t.amount <= ").push_bind(max_amount);
+    }
+    if let Some(q) = filters
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+    {
+        builder
+            .push(" AND strpos(lower(t.description), lower(")
+            .push_bind(q.to_string())
+            .push(")) > 0");
+    }
+
+    Ok(())
+}
+
+fn sort_column(sort: TransactionSort) -> &'static str {
+    match sort {
+        TransactionSort::Date => "t.transaction_date",
+        TransactionSort::Amount => "t.amount",
+    }
+}
+
+fn direction(order: SortOrder) -> (&'static str, &'static str) {
+    match order {
+        SortOrder::Asc => ("ASC", ">"),
+        SortOrder::Desc => ("DESC", "<"),
+    }
+}
+
+/// appends the keyset condition for `cursor` followed by `ORDER BY` and `LIMIT`
+pub fn push_transaction_page(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    sort: TransactionSort,
+    order: SortOrder,
+    cursor: Option<TransactionCursor>,
+    limit: i64,
+) -> Result<(), GlobalAppError> {
+    let column = sort_column(sort);
+    let (direction, comparison) = direction(order);
+
+    if let Some(cursor) = cursor {
+        if cursor.sort != sort || cursor.order != order {
+            return Err(GlobalAppError::validation(
+                "cursor",
+                "cursor was issued for a different sort order!",
+            ));
+        }
+
+        builder.push(format!(" AND ({column}, t.id) {comparison} ("));
+        match sort {
+            TransactionSort::Date => {
+                let value = DateTime::parse_from_rfc3339(&cursor.value)
+                    .map_err(|_| GlobalAppError::validation("cursor", "invalid cursor!"))?
+                    .with_timezone(&Utc);
+                builder.push_bind(value);
+            }
+            TransactionSort::Amount => {
+                let value = cursor
+                    .value
+                    .parse::<Decimal>()
+                    .map_err(|_| GlobalAppError::validation("cursor", "invalid cursor!"))?;
+                builder.push_bind(value);
+            }
+        }
+        builder.push(", ").push_bind(cursor.id).push(")");
+    }
+
+    builder.push(format!(
+        " ORDER BY {column} {direction}, t.id {direction} LIMIT "
+    ));
+    builder.push_bind(limit);
+
+    Ok(())
+}
+
+pub fn encode_cursor(
+    sort: TransactionSort,
+    order: SortOrder,
+    transaction: &TransactionInfo,
+) -> String {
+    let cursor = TransactionCursor {
+        sort,
+        order,
+        value: match sort {
+            TransactionSort::Date => transaction.transaction_date.to_rfc3339(),
+            TransactionSort::Amount => transaction.amount.to_string(),
+        },
+        id: transaction.id,
+    };
+
+    // serializing a struct of plain strings and enums cannot fail
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<TransactionCursor, GlobalAppError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| GlobalAppError::validation("cursor", "invalid cursor!"))
+}
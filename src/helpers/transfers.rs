@@ -0,0 +1,132 @@
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    helpers::{accounts::ensure_account_owned, currencies::parse_currency},
+    models::transfers::{PatchTransfer, TransferDirection, TransferInfo},
+};
+
+pub const TRANSFER_INFO_SELECT: &str = r#"SELECT
+        tr.id,
+        o.account_id AS from_account_id,
+        i.account_id AS to_account_id,
+        o.amount,
+        o.currency,
+        o.transaction_date,
+        o.description,
+        o.id AS outgoing_transaction_id,
+        i.id AS incoming_transaction_id,
+        tr.created_at
+        FROM transfers tr
+        INNER JOIN transactions o
+        ON o.transfer_id = tr.id AND o.transfer_direction = 'outgoing'
+        INNER JOIN transactions i
+        ON i.transfer_id = tr.id AND i.transfer_direction = 'incoming'"#;
+
+pub fn validate_amount(amount: Decimal) -> Result<(), GlobalAppError> {
+    if amount <= Decimal::ZERO {
+        return Err(GlobalAppError::validation(
+            "amount",
+            "amount must be greater than zero!",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_accounts(from: Option<Uuid>, to: Option<Uuid>) -> Result<(), GlobalAppError> {
+    if from.is_some() && from == to {
+        return Err(GlobalAppError::validation(
+            "to_account_id",
+            "cannot transfer money into the same account!",
+        ));
+    }
+    Ok(())
+}
+
+pub async fn fetch_transfer(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transfer_id: Uuid,
+) -> Result<TransferInfo, GlobalAppError> {
+    query_as::<_, TransferInfo>(&format!(
+        "{TRANSFER_INFO_SELECT} WHERE tr.id = $1 AND tr.user_id = $2"
+    ))
+    .bind(transfer_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| GlobalAppError::NotFound("transfer not found!".to_string()))
+}
+
+/// applies the patch to both legs together so they can never drift apart, whether it
+/// came in through the transfer or through one of its legs
+pub async fn patch_transfer(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transfer_id: Uuid,
+    patch: &PatchTransfer,
+) -> Result<(), GlobalAppError> {
+    if let Some(amount) = patch.amount {
+        validate_amount(amount)?;
+    }
+    let currency = patch
+        .currency
+        .as_deref()
+        .map(|code| parse_currency("currency", code))
+        .transpose()?;
+
+    let current = fetch_transfer(&mut *conn, user_id, transfer_id).await?;
+    validate_accounts(
+        patch.from_account_id.or(current.from_account_id),
+        patch.to_account_id.or(current.to_account_id),
+    )?;
+
+    for (account_id, field, direction) in [
+        (
+            patch.from_account_id,
+            "from_account_id",
+            TransferDirection::Outgoing,
+        ),
+        (
+            patch.to_account_id,
+            "to_account_id",
+            TransferDirection::Incoming,
+        ),
+    ] {
+        let Some(account_id) = account_id else {
+            continue;
+        };
+        ensure_account_owned(&mut *conn, user_id, account_id, field).await?;
+
+        query(
+            "UPDATE transactions SET account_id = $1 WHERE transfer_id = $2 AND transfer_direction = $3",
+        )
+        .bind(account_id)
+        .bind(transfer_id)
+        .bind(direction)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    query(
+        r#"UPDATE transactions SET
+        amount = COALESCE($1, amount),
+        transaction_date = COALESCE($2, transaction_date),
+        description = CASE WHEN $7 THEN $3 ELSE description END,
+        currency = COALESCE($4, currency)
+        WHERE transfer_id = $5 AND user_id = $6"#,
+    )
+    .bind(patch.amount)
+    .bind(patch.transaction_date)
+    .bind(patch.description.as_ref().and_then(Option::as_deref))
+    .bind(currency)
+    .bind(transfer_id)
+    .bind(user_id)
+    .bind(patch.description.is_some())
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
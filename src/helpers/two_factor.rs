@@ -0,0 +1,213 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, query, query_as};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError, helpers::sessions::hash_refresh_token, models::two_factor::TwoFactorRow,
+};
+
+pub const TOTP_ISSUER: &str = "Expense Tracker";
+pub const TOTP_STEP_SECS: u64 = 30;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// authenticator apps only understand the RFC 6238 defaults: SHA1, 6 digits, 30 seconds
+pub fn totp(secret: &str, account_name: &str) -> Result<TOTP, GlobalAppError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| GlobalAppError::Internal("invalid totp secret!".to_string()))?;
+
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP_SECS,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        account_name.to_string(),
+    ))
+}
+
+/// 160 random bits, base32 encoded the way authenticator apps expect it
+pub fn generate_totp_secret() -> String {
+    Secret::Raw(rand::random::<[u8; 20]>().to_vec())
+        .to_encoded()
+        .to_string()
+}
+
+/// the time step the code belongs to at `now`, allowing one step of clock drift either
+/// way. codes from `last_step` or earlier were used already and never match again
+pub fn match_totp_code(
+    secret: &str,
+    code: &str,
+    last_step: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<Option<i64>, GlobalAppError> {
+    let totp = totp(secret, "")?;
+    let current_step = now.timestamp() / TOTP_STEP_SECS as i64;
+
+    Ok((current_step - 1..=current_step + 1)
+        .filter(|step| last_step.is_none_or(|last_step| *step > last_step))
+        .find(|step| totp.check(code, *step as u64 * TOTP_STEP_SECS)))
+}
+
+/// recovery codes are compared without dashes, spaces or case
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|char| char.to_ascii_lowercase())
+        .collect()
+}
+
+/// 80 random bits, too many to guess so a plain hash is enough to store them
+fn generate_recovery_code() -> String {
+    let code = hex::encode(rand::random::<[u8; 10]>());
+    format!(
+        "{}-{}-{}-{}",
+        &code[..5],
+        &code[5..10],
+        &code[10..15],
+        &code[15..]
+    )
+}
+
+/// replaces every recovery code of the user with a fresh set and returns them in clear,
+/// this is the only time they are ever shown
+pub async fn generate_recovery_codes(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Vec<String>, GlobalAppError> {
+    query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = generate_recovery_code();
+
+        query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(hash_refresh_token(&normalize_recovery_code(&code)))
+            .execute(&mut *conn)
+            .await?;
+
+        codes.push(code);
+    }
+
+    Ok(codes)
+}
+
+/// checks a code from the authenticator app or an unused recovery code, either one is
+/// spent by a successful check. locks the user row so the same code cannot race itself
+pub async fn verify_second_factor(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, GlobalAppError> {
+    let row = query_as::<_, TwoFactorRow>(
+        "SELECT totp_secret, totp_enabled_at, totp_last_step FROM users WHERE id = $1 FOR UPDATE",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (Some(secret), Some(_)) = (row.totp_secret, row.totp_enabled_at) else {
+        return Ok(false);
+    };
+
+    let code = code.trim();
+    if code.len() == 6 && code.bytes().all(|byte| byte.is_ascii_digit()) {
+        let Some(step) = match_totp_code(&secret, code, row.totp_last_step, Utc::now())? else {
+            return Ok(false);
+        };
+
+        query("UPDATE users SET totp_last_step = $1 WHERE id = $2")
+            .bind(step)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await?;
+
+        return Ok(true);
+    }
+
+    Ok(query(
+        "UPDATE recovery_codes SET used_at = NOW() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(hash_refresh_token(&normalize_recovery_code(code)))
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_at(secret: &str, step: i64) -> String {
+        totp(secret, "")
+            .unwrap()
+            .generate(step as u64 * TOTP_STEP_SECS)
+    }
+
+    fn at_step(step: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(step * TOTP_STEP_SECS as i64 + 7, 0).unwrap()
+    }
+
+    #[test]
+    fn codes_match_their_step_with_one_step_of_drift() {
+        let secret = generate_totp_secret();
+        let now = at_step(1_000);
+
+        for step in 999..=1_001 {
+            assert_eq!(
+                match_totp_code(&secret, &code_at(&secret, step), None, now).unwrap(),
+                Some(step)
+            );
+        }
+        for step in [998, 1_002] {
+            assert_eq!(
+                match_totp_code(&secret, &code_at(&secret, step), None, now).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn used_steps_never_match_again() {
+        let secret = generate_totp_secret();
+        let now = at_step(1_000);
+        let code = code_at(&secret, 1_000);
+
+        assert_eq!(
+            match_totp_code(&secret, &code, Some(999), now).unwrap(),
+            Some(1_000)
+        );
+        assert_eq!(
+            match_totp_code(&secret, &code, Some(1_000), now).unwrap(),
+            None
+        );
+        assert_eq!(
+            match_totp_code(&secret, &code_at(&secret, 999), Some(999), now).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn recovery_codes_ignore_dashes_spaces_and_case() {
+        assert_eq!(normalize_recovery_code(" AB12c-34dE f "), "ab12c34def");
+        assert_eq!(normalize_recovery_code("--"), "");
+    }
+
+    #[test]
+    fn recovery_codes_carry_80_bits() {
+        let code = generate_recovery_code();
+
+        assert_eq!(code.len(), 23);
+        assert_eq!(normalize_recovery_code(&code).len(), 20);
+        assert_eq!(code.split('-').count(), 4);
+        assert_ne!(code, generate_recovery_code());
+    }
+}
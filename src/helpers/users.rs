@@ -2,24 +2,83 @@ use argon2::{
     Argon2, PasswordHash,
     password_hash::{SaltString, rand_core::OsRng},
 };
-use axum::http::StatusCode;
-use chrono::{Duration, Utc};
-use jsonwebtoken::{EncodingKey, Header, encode};
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+use crate::errors::{FieldError, GlobalAppError};
+
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 32;
+/// the longest address SMTP can deliver to
+pub const EMAIL_MAX_LENGTH: usize = 254;
+
+/// how usernames and emails are stored and looked up: trimmed, NFKC normalized so
+/// look-alike forms such as fullwidth letters collapse, and lowercased
+pub fn normalize_identifier(value: &str) -> String {
+    value.trim().nfkc().collect::<String>().to_lowercase()
+}
 
-use crate::errors::GlobalAppError;
+/// expects a normalized username. letters and digits in any script plus `_`, `.` and `-`,
+/// never `@` so it cannot be mistaken for an email at login
+pub fn validate_username(username: &str) -> Result<(), FieldError> {
+    let length = username.chars().count();
+    if !(USERNAME_MIN_LENGTH..=USERNAME_MAX_LENGTH).contains(&length) {
+        return Err(FieldError::new(
+            "username",
+            &format!(
+                "username must be between {USERNAME_MIN_LENGTH} and {USERNAME_MAX_LENGTH} characters!"
+            ),
+        ));
+    }
+    if !username.starts_with(char::is_alphanumeric) {
+        return Err(FieldError::new(
+            "username",
+            "username must start with a letter or digit!",
+        ));
+    }
+    if let Some(invalid) = username
+        .chars()
+        .find(|char| !(char.is_alphanumeric() || matches!(char, '_' | '.' | '-')))
+    {
+        return Err(FieldError::new(
+            "username",
+            &format!(
+                "'{invalid}' is not allowed in usernames, use letters, digits, '_', '.' or '-'!"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// expects a normalized email
+pub fn validate_email(email: &str) -> Result<(), FieldError> {
+    let invalid = || FieldError::new("email", "email is not a valid email address!");
+
+    if email.len() > EMAIL_MAX_LENGTH {
+        return Err(FieldError::new(
+            "email",
+            &format!("email must be at most {EMAIL_MAX_LENGTH} characters!"),
+        ));
+    }
+    let address = email.parse::<lettre::Address>().map_err(|_| invalid())?;
+    // lettre accepts bare hosts such as `user@localhost`, nothing outside a lab has those
+    if !address.domain().contains('.') || address.domain().ends_with('.') {
+        return Err(invalid());
+    }
+    Ok(())
+}
 
 pub async fn hash_password(password: String) -> Result<String, GlobalAppError> {
     tokio::task::spawn_blocking(move || -> Result<String, GlobalAppError> {
         let salt = SaltString::generate(&mut OsRng);
         let argon = Argon2::default();
         Ok(PasswordHash::generate(argon, password, salt.as_salt())
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "server_error".to_string(),
-                )
-            })?
+            .map_err(|_| GlobalAppError::Internal("server_error".to_string()))?
             .to_string())
     })
     .await
@@ -32,55 +91,186 @@ pub async fn verify_password(
 ) -> Result<(), GlobalAppError> {
     tokio::task::spawn_blocking(move || -> Result<(), GlobalAppError> {
         PasswordHash::new(&hashed_password)
-            .map_err(|_| {
-                GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "error parsing hashed password!".to_string(),
-                )
-            })?
+            .map_err(|_| GlobalAppError::Internal("error parsing hashed password!".to_string()))?
             .verify_password(&[&Argon2::default()], password)
             .map_err(|err| match err {
                 argon2::password_hash::Error::Password => {
-                    GlobalAppError::new(StatusCode::BAD_REQUEST, "invalid password".to_string())
+                    GlobalAppError::Unauthorized("invalid password".to_string())
                 }
-                _ => GlobalAppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "password verification error!".to_string(),
-                ),
+                _ => GlobalAppError::Internal("password verification error!".to_string()),
             })
     })
     .await
     .unwrap()
 }
 
-#[derive(Serialize, Deserialize)]
+/// how long the password step of a two-factor login stays good for
+pub const TWO_FACTOR_CHALLENGE_TTL_MINUTES: i64 = 5;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// a regular access token
+    #[default]
+    Access,
+    /// only good for `/users/login/2fa`, `validate_jwt` refuses it everywhere else
+    TwoFactor,
+}
+
+/// checks the password against a throwaway hash so unknown usernames take as long to
+/// answer as wrong passwords
+pub async fn verify_dummy_password(password: String) {
+    static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+        PasswordHash::generate(
+            Argon2::default(),
+            "not a real password",
+            SaltString::generate(&mut OsRng).as_salt(),
+        )
+        .map(|hash| hash.to_string())
+        .unwrap_or_default()
+    });
+
+    let _ = verify_password(password, DUMMY_HASH.clone()).await;
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
     pub iat: i64,
+    /// `iat` to the microsecond, so a token issued in the same second as a revocation
+    /// can be told apart from one issued before it. older tokens only have `iat`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_micros: Option<i64>,
     pub exp: i64,
+    pub jti: String,
+    pub sid: String,
+    #[serde(default)]
+    pub scope: TokenScope,
+}
+
+impl Claims {
+    /// a token without `iat_micros` counts as issued at the very start of its second
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        self.iat_micros
+            .and_then(DateTime::from_timestamp_micros)
+            .or_else(|| DateTime::from_timestamp(self.iat, 0))
+            .unwrap_or_default()
+    }
+}
+
+pub fn create_jwt(
+    uuid: String,
+    session_id: String,
+    hmac_key: String,
+) -> Result<String, GlobalAppError> {
+    sign_claims(
+        uuid,
+        session_id,
+        TokenScope::Access,
+        Duration::hours(1),
+        &hmac_key,
+    )
+}
+
+/// the token handed out after the password step when the user has two-factor enabled,
+/// it belongs to no session yet
+pub fn create_challenge_jwt(uuid: String, hmac_key: &str) -> Result<String, GlobalAppError> {
+    sign_claims(
+        uuid,
+        String::new(),
+        TokenScope::TwoFactor,
+        Duration::minutes(TWO_FACTOR_CHALLENGE_TTL_MINUTES),
+        hmac_key,
+    )
 }
 
-pub fn create_jwt(uuid: String, hmac_key: String) -> Result<String, GlobalAppError> {
-    let header = Header::new(jsonwebtoken::Algorithm::HS384);
+fn sign_claims(
+    uuid: String,
+    session_id: String,
+    scope: TokenScope,
+    ttl: Duration,
+    hmac_key: &str,
+) -> Result<String, GlobalAppError> {
+    let header = Header::new(Algorithm::HS384);
 
     let now = Utc::now();
     let claims = Claims {
         sub: uuid,
         iat: now.timestamp(),
-        exp: (now + Duration::hours(1)).timestamp(),
+        iat_micros: Some(now.timestamp_micros()),
+        exp: (now + ttl).timestamp(),
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id,
+        scope,
     };
 
-    let key = EncodingKey::from_base64_secret(hmac_key.as_str()).map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "error parsing hmac key into encoded key type!".to_string(),
-        )
+    let key = EncodingKey::from_base64_secret(hmac_key).map_err(|_| {
+        GlobalAppError::Internal("error parsing hmac key into encoded key type!".to_string())
     })?;
 
-    encode(&header, &claims, &key).map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "error creating jwt token!".to_string(),
+    encode(&header, &claims, &key)
+        .map_err(|_| GlobalAppError::Internal("error creating jwt token!".to_string()))
+}
+
+pub fn decode_jwt(token: &str, hmac_key: &str) -> Result<Claims, GlobalAppError> {
+    Ok(decode::<Claims>(
+        token,
+        &DecodingKey::from_base64_secret(hmac_key)
+            .map_err(|_| GlobalAppError::Internal("invalid hmac!".to_string()))?,
+        &Validation::new(Algorithm::HS384),
+    )
+    .map_err(|_| {
+        GlobalAppError::Unauthorized(
+            "error decoding token! token might have expired or invalid!".to_string(),
         )
-    })
+    })?
+    .claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_trimmed_folded_and_lowercased() {
+        assert_eq!(normalize_identifier("  Alice "), "alice");
+        assert_eq!(normalize_identifier("ＡＬＩＣＥ"), "alice");
+        assert_eq!(normalize_identifier("Bob@Example.COM"), "bob@example.com");
+    }
+
+    #[test]
+    fn usernames_take_letters_digits_and_a_few_separators() {
+        for username in ["bob", "b0b_the.builder-2", "zoë", "山田太郎"] {
+            assert!(validate_username(username).is_ok(), "{username}");
+        }
+    }
+
+    #[test]
+    fn usernames_are_rejected_with_a_reason() {
+        let message = |username: &str| validate_username(username).unwrap_err().message;
+
+        assert!(message("ab").contains("between 3 and 32"));
+        assert!(message(&"a".repeat(33)).contains("between 3 and 32"));
+        assert!(message("_bob").contains("start with a letter or digit"));
+        assert!(message("bob@example.com").contains("'@' is not allowed"));
+        assert!(message("bob smith").contains("' ' is not allowed"));
+    }
+
+    #[test]
+    fn emails_need_a_dotted_domain() {
+        assert!(validate_email("bob@example.com").is_ok());
+        assert!(validate_email("bob.smith+tag@mail.example.co.uk").is_ok());
+
+        for email in [
+            "bob",
+            "bob@",
+            "@example.com",
+            "bob@localhost",
+            "bob@example.",
+            "bob smith@example.com",
+        ] {
+            assert!(validate_email(email).is_err(), "{email}");
+        }
+        assert!(validate_email(&format!("{}@example.com", "a".repeat(250))).is_err());
+    }
 }
@@ -0,0 +1,102 @@
+use chrono::{Duration, Utc};
+use sqlx::{PgConnection, query, query_as};
+use uuid::Uuid;
+
+use crate::{
+    errors::GlobalAppError,
+    helpers::sessions::{generate_refresh_token, hash_refresh_token},
+    mailer::{EmailMessage, Mailer},
+    models::users::UserTokenPurpose,
+};
+
+pub const VERIFY_EMAIL_TTL_HOURS: i64 = 24;
+pub const RESET_PASSWORD_TTL_HOURS: i64 = 1;
+
+/// hands out a fresh token for `purpose`, earlier unused ones stop working
+pub async fn issue_user_token(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    purpose: UserTokenPurpose,
+) -> Result<String, GlobalAppError> {
+    let ttl = match purpose {
+        UserTokenPurpose::VerifyEmail => Duration::hours(VERIFY_EMAIL_TTL_HOURS),
+        UserTokenPurpose::ResetPassword => Duration::hours(RESET_PASSWORD_TTL_HOURS),
+    };
+    let token = generate_refresh_token();
+
+    query("DELETE FROM user_tokens WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL")
+        .bind(user_id)
+        .bind(purpose)
+        .execute(&mut *conn)
+        .await?;
+
+    query(
+        "INSERT INTO user_tokens (user_id, purpose, token_hash, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id)
+    .bind(purpose)
+    .bind(hash_refresh_token(&token))
+    .bind(Utc::now() + ttl)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(token)
+}
+
+/// marks the token used and returns its user, None when it is unknown, expired or used
+pub async fn consume_user_token(
+    conn: &mut PgConnection,
+    token: &str,
+    purpose: UserTokenPurpose,
+) -> Result<Option<Uuid>, GlobalAppError> {
+    Ok(query_as::<_, (Uuid,)>(
+        r#"UPDATE user_tokens SET used_at = NOW()
+        WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id"#,
+    )
+    .bind(hash_refresh_token(token))
+    .bind(purpose)
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|(user_id,)| user_id))
+}
+
+pub async fn send_verification_email(
+    conn: &mut PgConnection,
+    mailer: &Mailer,
+    user_id: Uuid,
+    email: &str,
+) -> Result<(), GlobalAppError> {
+    let token = issue_user_token(conn, user_id, UserTokenPurpose::VerifyEmail).await?;
+
+    mailer
+        .send(EmailMessage {
+            to: email.to_string(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Welcome to Expense Tracker!\n\nConfirm your email address by opening\n{}/verify-email?token={token}\n\nor by sending this token to POST /users/verify-email:\n{token}\n\nThe link expires in {VERIFY_EMAIL_TTL_HOURS} hours.\n",
+                mailer.app_url
+            ),
+        })
+        .await
+}
+
+pub async fn send_password_reset_email(
+    conn: &mut PgConnection,
+    mailer: &Mailer,
+    user_id: Uuid,
+    email: &str,
+) -> Result<(), GlobalAppError> {
+    let token = issue_user_token(conn, user_id, UserTokenPurpose::ResetPassword).await?;
+
+    mailer
+        .send(EmailMessage {
+            to: email.to_string(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password of your Expense Tracker account.\n\nChoose a new password by opening\n{}/reset-password?token={token}\n\nor by sending this token to POST /users/password/reset:\n{token}\n\nThe link expires in {RESET_PASSWORD_TTL_HOURS} hour. If this was not you, ignore this email.\n",
+                mailer.app_url
+            ),
+        })
+        .await
+}
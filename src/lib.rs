@@ -1,6 +1,8 @@
 pub mod errors;
+pub mod extractors;
 pub mod handlers;
 pub mod helpers;
+pub mod mailer;
 pub mod middlewares;
 pub mod models;
 pub mod routers;
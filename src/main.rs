@@ -1,4 +1,11 @@
-use expense_tracker_backend::{middlewares::GlobalAppState, routers};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use expense_tracker_backend::{
+    helpers::{password_policy::PasswordPolicy, recurring::run_recurring_scheduler},
+    mailer::Mailer,
+    middlewares::GlobalAppState,
+    routers,
+};
 use sqlx::postgres::PgPoolOptions;
 
 #[tokio::main]
@@ -13,12 +20,38 @@ async fn main() {
         .await
         .unwrap();
 
+    let scheduler_interval = dotenvy::var("RECURRING_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(60);
+    tokio::spawn(run_recurring_scheduler(
+        pool.clone(),
+        Duration::from_secs(scheduler_interval),
+    ));
+
+    let require_verified_email =
+        dotenvy::var("REQUIRE_EMAIL_VERIFICATION").is_ok_and(|value| value == "true");
+    let mailer = Mailer::from_env(require_verified_email).unwrap();
+    let trust_forwarded_for =
+        dotenvy::var("TRUST_FORWARDED_FOR").is_ok_and(|value| value == "true");
+    let password_policy = Arc::new(PasswordPolicy::from_env().unwrap());
+
     let app_state = GlobalAppState {
         pool,
         hmac: hmac_key,
+        mailer,
+        require_verified_email,
+        trust_forwarded_for,
+        password_policy,
     };
 
     let app = routers::app_router(app_state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // login throttling needs the client address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,14 +1,19 @@
 use axum::{
     extract::{Request, State},
-    http::StatusCode,
     middleware::Next,
     response::Response,
 };
 use axum_extra::headers::{Authorization, HeaderMapExt, authorization::Bearer};
-use jsonwebtoken::{Algorithm, DecodingKey, Validation};
 use uuid::Uuid;
 
-use crate::{errors::GlobalAppError, helpers::users::Claims, middlewares::GlobalAppState};
+use crate::{
+    errors::GlobalAppError,
+    helpers::{
+        sessions::is_access_token_revoked,
+        users::{TokenScope, decode_jwt},
+    },
+    middlewares::GlobalAppState,
+};
 
 pub async fn validate_jwt(
     State(state): State<GlobalAppState>,
@@ -18,38 +23,35 @@ pub async fn validate_jwt(
     let token = request
         .headers()
         .typed_get::<Authorization<Bearer>>()
-        .ok_or_else(|| {
-            GlobalAppError::new(
-                StatusCode::BAD_REQUEST,
-                "auth bearer token missing!".to_owned(),
-            )
-        })?
+        .ok_or_else(|| GlobalAppError::Unauthorized("auth bearer token missing!".to_owned()))?
         .token()
         .to_owned();
 
-    let decoded_data = jsonwebtoken::decode::<Claims>(
-        &token,
-        &DecodingKey::from_base64_secret(&state.hmac).map_err(|_| {
-            GlobalAppError::new(StatusCode::BAD_REQUEST, "invalid hmac!".to_string())
-        })?,
-        &Validation::new(Algorithm::HS384),
-    )
-    .map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::UNAUTHORIZED,
-            "error decoding token! token might have expired or invalid!".to_string(),
-        )
-    })?;
-
-    let claim = decoded_data.claims;
-    let uuid = Uuid::parse_str(&claim.sub).map_err(|_| {
-        GlobalAppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "error parsing uuid!".to_string(),
-        )
-    })?;
+    let claim = decode_jwt(&token, &state.hmac)?;
+
+    if claim.scope != TokenScope::Access {
+        return Err(GlobalAppError::Unauthorized(
+            "two-factor code required! finish logging in at /users/login/2fa".to_string(),
+        ));
+    }
+
+    let uuid = Uuid::parse_str(&claim.sub)
+        .map_err(|_| GlobalAppError::Internal("error parsing uuid!".to_string()))?;
+
+    let jti = Uuid::parse_str(&claim.jti)
+        .map_err(|_| GlobalAppError::Unauthorized("invalid token id!".to_string()))?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    if is_access_token_revoked(&mut conn, uuid, jti, claim.issued_at()).await? {
+        return Err(GlobalAppError::Unauthorized(
+            "token has been revoked! please log in again".to_string(),
+        ));
+    }
+    drop(conn);
 
     request.extensions_mut().insert(uuid);
+    request.extensions_mut().insert(claim);
 
     Ok(next.run(request).await)
 }
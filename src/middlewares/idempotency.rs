@@ -0,0 +1,309 @@
+use std::mem;
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, query, query_as};
+use uuid::Uuid;
+
+use crate::{errors::GlobalAppError, middlewares::GlobalAppState};
+
+pub static IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+pub static IDEMPOTENCY_REPLAYED_HEADER: HeaderName =
+    HeaderName::from_static("idempotency-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+/// same as the largest body any route accepts, the statement imports
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+type StoredResponse = (String, Option<i16>, Option<Vec<String>>, Option<Vec<u8>>);
+
+/// headers about the connection the response went out on rather than the response
+/// itself, the length is set again from the replayed body
+const UNSTORED_HEADERS: [HeaderName; 9] = [
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    HeaderName::from_static("keep-alive"),
+];
+
+fn request_hash(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b" ");
+    hasher.update(uri);
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// "name: value" lines, values that are not visible ascii cannot be stored and are dropped
+fn stored_headers(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !UNSTORED_HEADERS.contains(name))
+        .filter_map(|(name, value)| Some(format!("{name}: {}", value.to_str().ok()?)))
+        .collect()
+}
+
+fn replay(
+    status_code: i16,
+    headers: Vec<String>,
+    body: Vec<u8>,
+) -> Result<Response, GlobalAppError> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = u16::try_from(status_code)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(|| GlobalAppError::Internal("invalid stored status code!".to_string()))?;
+    for line in headers {
+        let Some((name, value)) = line.split_once(": ") else {
+            continue;
+        };
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response.headers_mut().insert(
+        IDEMPOTENCY_REPLAYED_HEADER.clone(),
+        HeaderValue::from_static("true"),
+    );
+
+    Ok(response)
+}
+
+/// gives a claimed key back when the request never got to store its response, because
+/// the client went away and the handler was dropped, it panicked or storing failed.
+/// the lease on the row covers the process dying before this runs
+struct Claim {
+    pool: PgPool,
+    user_id: Uuid,
+    key: String,
+    released: bool,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let (pool, user_id, key) = (self.pool.clone(), self.user_id, mem::take(&mut self.key));
+        runtime.spawn(async move {
+            if let Err(error) = query(
+                "DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND status_code IS NULL",
+            )
+            .bind(user_id)
+            .bind(&key)
+            .execute(&pool)
+            .await
+            {
+                eprintln!("error releasing idempotency key: {error}");
+            }
+        });
+    }
+}
+
+/// makes POST requests that carry an `Idempotency-Key` header safe to retry. the first
+/// response for a key is kept for 24 hours and replayed for every repeat of the same
+/// request, reusing the key for a different request is rejected. has to run after
+/// `validate_jwt` since keys are scoped to the user
+pub async fn idempotency(
+    State(state): State<GlobalAppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, GlobalAppError> {
+    let (Some(key), Some(&user_id)) = (
+        request.headers().get(&IDEMPOTENCY_KEY_HEADER),
+        request.extensions().get::<Uuid>(),
+    ) else {
+        return Ok(next.run(request).await);
+    };
+    if request.method() != Method::POST {
+        return Ok(next.run(request).await);
+    }
+
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| {
+            GlobalAppError::validation(
+                "Idempotency-Key",
+                &format!("Idempotency-Key must be 1 to {MAX_KEY_LENGTH} visible ascii characters!"),
+            )
+        })?
+        .to_string();
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_SIZE)
+        .await
+        .map_err(|_| GlobalAppError::BadRequest("request body is too large!".to_string()))?;
+    let hash = request_hash(&parts.method, &parts.uri.to_string(), &body);
+
+    let mut conn = state.pool.acquire().await?;
+
+    query("DELETE FROM idempotency_keys WHERE user_id = $1 AND expires_at <= NOW()")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    // only one of several concurrent requests with the same key gets to claim it, a repeat
+    // of the same request takes over a claim whose lease ran out without a response
+    let claimed = query(
+        r#"INSERT INTO idempotency_keys (user_id, key, request_hash, locked_until)
+        VALUES ($1, $2, $3, NOW() + INTERVAL '5 minutes')
+        ON CONFLICT (user_id, key) DO UPDATE
+        SET locked_until = EXCLUDED.locked_until
+        WHERE idempotency_keys.status_code IS NULL
+        AND idempotency_keys.locked_until <= NOW()
+        AND idempotency_keys.request_hash = EXCLUDED.request_hash"#,
+    )
+    .bind(user_id)
+    .bind(&key)
+    .bind(&hash)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected()
+        == 1;
+
+    if !claimed {
+        let stored = query_as::<_, StoredResponse>(
+            r#"SELECT request_hash, status_code, response_headers, response_body
+            FROM idempotency_keys
+            WHERE user_id = $1 AND key = $2"#,
+        )
+        .bind(user_id)
+        .bind(&key)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        return match stored {
+            Some((stored_hash, _, _, _)) if stored_hash != hash => {
+                Err(GlobalAppError::Unprocessable(
+                    "Idempotency-Key was already used for a different request!".to_string(),
+                ))
+            }
+            Some((_, Some(status_code), headers, body)) => replay(
+                status_code,
+                headers.unwrap_or_default(),
+                body.unwrap_or_default(),
+            ),
+            // still running, or it failed and was released in the meantime
+            _ => Err(GlobalAppError::Conflict(
+                "a request with this Idempotency-Key is still being processed!".to_string(),
+            )),
+        };
+    }
+    drop(conn);
+
+    let mut claim = Claim {
+        pool: state.pool.clone(),
+        user_id,
+        key: key.clone(),
+        released: false,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // server errors are not kept so the client can retry them with the same key
+    if response.status().is_server_error() {
+        query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2")
+            .bind(user_id)
+            .bind(&key)
+            .execute(&state.pool)
+            .await?;
+        claim.released = true;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| GlobalAppError::Internal("error reading response body!".to_string()))?;
+    query(
+        r#"UPDATE idempotency_keys
+        SET status_code = $1, response_headers = $2, response_body = $3
+        WHERE user_id = $4 AND key = $5"#,
+    )
+    .bind(parts.status.as_u16() as i16)
+    .bind(stored_headers(&parts.headers))
+    .bind(body.as_ref())
+    .bind(user_id)
+    .bind(&key)
+    .execute(&state.pool)
+    .await?;
+    claim.released = true;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_headers_leave_out_hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("12"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("b=2"));
+
+        assert_eq!(
+            stored_headers(&headers),
+            [
+                "content-type: text/csv",
+                "set-cookie: a=1",
+                "set-cookie: b=2"
+            ]
+        );
+    }
+
+    #[test]
+    fn replays_restore_every_stored_header() {
+        let response = replay(
+            201,
+            vec![
+                "content-disposition: attachment; filename=\"a.csv\"".to_string(),
+                "set-cookie: a=1".to_string(),
+                "set-cookie: b=2".to_string(),
+                "not a header".to_string(),
+            ],
+            b"id".to_vec(),
+        )
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"a.csv\""
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .count(),
+            2
+        );
+        assert_eq!(response.headers()[&IDEMPOTENCY_REPLAYED_HEADER], "true");
+    }
+}
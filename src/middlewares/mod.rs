@@ -1,9 +1,22 @@
+use std::sync::Arc;
+
 use sqlx::PgPool;
 
+use crate::{helpers::password_policy::PasswordPolicy, mailer::Mailer};
+
 pub mod auth;
+pub mod idempotency;
+pub mod request_id;
 
 #[derive(Clone)]
 pub struct GlobalAppState {
     pub pool: PgPool,
     pub hmac: String,
+    pub mailer: Mailer,
+    /// login is refused until the email address is verified
+    pub require_verified_email: bool,
+    /// take the client address from X-Forwarded-For, only safe behind a proxy that sets it
+    pub trust_forwarded_for: bool,
+    /// shared, the breached password list can be large
+    pub password_policy: Arc<PasswordPolicy>,
 }
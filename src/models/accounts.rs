@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "account_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AccountType {
+    Checking,
+    Savings,
+    CreditCard,
+    Cash,
+    Investment,
+}
+
+#[derive(Deserialize)]
+pub struct CreateAccount {
+    pub name: String,
+    pub account_type: AccountType,
+    #[serde(default)]
+    pub opening_balance: Decimal,
+}
+
+#[derive(Deserialize)]
+pub struct PatchAccount {
+    pub name: Option<String>,
+    pub account_type: Option<AccountType>,
+    pub opening_balance: Option<Decimal>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct AccountInfo {
+    pub id: Uuid,
+    pub name: String,
+    #[sqlx(rename = "type")]
+    pub account_type: AccountType,
+    pub opening_balance: Decimal,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct AccountBalance {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub account: AccountInfo,
+    /// income and incoming transfers recorded against the account, in the base currency
+    pub total_inflow: Decimal,
+    /// expenses, savings included, and outgoing transfers recorded against the account
+    pub total_outflow: Decimal,
+    /// opening balance is taken to be in the base currency already
+    pub balance: Decimal,
+    pub transaction_count: i64,
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::models::categories::GetUserCategories;
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug, Default)]
+#[sqlx(type_name = "budget_period", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Weekly,
+    #[default]
+    Monthly,
+    Yearly,
+}
+
+#[derive(Deserialize)]
+pub struct CreateBudget {
+    /// expense category name or slug
+    pub category: String,
+    /// in the user's base currency
+    pub amount: Decimal,
+    #[serde(default)]
+    pub period: BudgetPeriod,
+    #[serde(default)]
+    pub rollover: bool,
+    /// first period the budget applies to, unspent amounts roll over from here
+    pub starts_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct PatchBudget {
+    pub amount: Option<Decimal>,
+    pub period: Option<BudgetPeriod>,
+    pub rollover: Option<bool>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct BudgetInfo {
+    #[sqlx(rename = "budget_id")]
+    pub id: Uuid,
+    pub amount: Decimal,
+    pub period: BudgetPeriod,
+    pub rollover: bool,
+    pub starts_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    pub category: GetUserCategories,
+}
+
+#[derive(Deserialize)]
+pub struct BudgetStatusParams {
+    /// point in time whose period is reported, defaults to now
+    pub at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+pub struct BudgetStatusRow {
+    #[sqlx(flatten)]
+    pub budget: BudgetInfo,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub spent: Decimal,
+    pub rolled_over: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct BudgetStatus {
+    pub budget: BudgetInfo,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// unspent amount carried over from earlier periods, always zero without rollover
+    pub rolled_over: Decimal,
+    pub available: Decimal,
+    /// converted into the base currency at the rate of each transaction's date
+    pub spent: Decimal,
+    pub remaining: Decimal,
+    pub percent_used: Decimal,
+}
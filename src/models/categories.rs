@@ -11,7 +11,7 @@ pub struct CreateCategoryDetails {
     pub is_savings: bool,
 }
 
-#[derive(Serialize, Deserialize, sqlx::Type)]
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug)]
 #[sqlx(type_name = "category_type", rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum CategoryType {
@@ -19,7 +19,7 @@ pub enum CategoryType {
     Income,
 }
 
-#[derive(FromRow, Deserialize, Serialize)]
+#[derive(FromRow, Deserialize, Serialize, Clone)]
 pub struct GetUserCategories {
     pub id: Uuid,
     pub name: String,
@@ -35,3 +35,43 @@ pub struct PatchUserCategories {
     pub category_type: Option<CategoryType>,
     pub is_savings: Option<bool>,
 }
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    #[default]
+    Skip,
+    Error,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCategoryParams {
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CategoryCreateStatus {
+    Created,
+    AlreadyExists,
+    Invalid,
+}
+
+#[derive(Serialize)]
+pub struct CategoryCreateResult {
+    pub index: usize,
+    pub name: String,
+    pub slug: String,
+    pub status: CategoryCreateStatus,
+    pub id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateCategoriesResponse {
+    pub created: usize,
+    pub already_existing: usize,
+    pub invalid: usize,
+    pub results: Vec<CategoryCreateResult>,
+}
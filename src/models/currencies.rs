@@ -0,0 +1,43 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Deserialize, Serialize)]
+pub struct BaseCurrency {
+    /// ISO 4217 code every report is converted into
+    pub base_currency: String,
+}
+
+#[derive(Deserialize)]
+pub struct ExchangeRateRequest {
+    pub from_currency: String,
+    pub to_currency: String,
+    /// units of to_currency one unit of from_currency is worth
+    pub rate: Decimal,
+    pub effective_date: NaiveDate,
+}
+
+#[derive(Deserialize)]
+pub struct ExchangeRateFilters {
+    pub from_currency: Option<String>,
+    pub to_currency: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExchangeRateInfo {
+    pub id: Uuid,
+    pub from_currency: String,
+    pub to_currency: String,
+    pub rate: Decimal,
+    pub effective_date: NaiveDate,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct MissingExchangeRate {
+    pub from_currency: String,
+    pub to_currency: String,
+    pub first_needed_on: NaiveDate,
+}
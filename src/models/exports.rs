@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{
+    categories::CategoryType, transactions::TransactionInfo, transfers::TransferDirection,
+};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+    Xlsx,
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// one transaction flattened into spreadsheet columns, in the order they are exported
+#[derive(Serialize)]
+pub struct TransactionExportRow {
+    pub id: Uuid,
+    pub transaction_date: DateTime<Utc>,
+    pub description: Option<String>,
+    pub amount: Decimal,
+    pub currency: String,
+    pub base_amount: Option<Decimal>,
+    pub base_currency: String,
+    pub exchange_rate: Option<Decimal>,
+    /// the category columns are empty on the legs of a transfer
+    pub category: Option<String>,
+    pub category_type: Option<CategoryType>,
+    pub is_savings: Option<bool>,
+    pub account_id: Option<Uuid>,
+    pub transfer_id: Option<Uuid>,
+    pub transfer_direction: Option<TransferDirection>,
+}
+
+impl From<TransactionInfo> for TransactionExportRow {
+    fn from(transaction: TransactionInfo) -> Self {
+        Self {
+            id: transaction.id,
+            transaction_date: transaction.transaction_date,
+            description: transaction.description,
+            amount: transaction.amount,
+            currency: transaction.currency,
+            base_amount: transaction.base_amount,
+            base_currency: transaction.base_currency,
+            exchange_rate: transaction.exchange_rate,
+            category: transaction.name,
+            category_type: transaction.category_type,
+            is_savings: transaction.is_savings,
+            account_id: transaction.account_id,
+            transfer_id: transaction.transfer_id,
+            transfer_direction: transaction.transfer_direction,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{categories::CategoryType, transactions::TransactionRequest};
+
+/// a column is referenced by its header or by its zero based position
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct CsvMapping {
+    #[serde(default = "default_true")]
+    pub has_header: bool,
+    /// defaults to a comma
+    pub delimiter: Option<char>,
+    pub date_column: ColumnRef,
+    /// chrono strftime format, RFC 3339 or %Y-%m-%d when left out
+    pub date_format: Option<String>,
+    /// a single signed amount where negative is money going out, or separate debit and
+    /// credit columns
+    pub amount_column: Option<ColumnRef>,
+    pub debit_column: Option<ColumnRef>,
+    pub credit_column: Option<ColumnRef>,
+    /// amount_column is positive for money going out, as on most credit card statements
+    #[serde(default)]
+    pub invert_amounts: bool,
+    /// amounts are written like 1.234,56
+    #[serde(default)]
+    pub decimal_comma: bool,
+    pub description_column: Option<ColumnRef>,
+    /// category name or slug per row, falling back to expense_category or income_category
+    /// by the direction of the amount when empty
+    pub category_column: Option<ColumnRef>,
+    pub expense_category: Option<String>,
+    pub income_category: Option<String>,
+    /// ISO 4217 code per row, falling back to currency and then the base currency
+    pub currency_column: Option<ColumnRef>,
+    pub currency: Option<String>,
+    pub account_id: Option<Uuid>,
+}
+
+/// options for statement formats that carry no categories of their own
+#[derive(Deserialize, Default)]
+pub struct StatementOptions {
+    /// used for money going out of the account
+    pub expense_category: Option<String>,
+    /// used for money coming in
+    pub income_category: Option<String>,
+    /// ISO 4217 code, for OFX the statement's own currency wins when left out
+    pub currency: Option<String>,
+    pub account_id: Option<Uuid>,
+    /// QIF only, chrono strftime format, guessed from the file when left out
+    pub date_format: Option<String>,
+    /// QIF only, amounts are written like 1.234,56
+    #[serde(default)]
+    pub decimal_comma: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportParams {
+    /// parse and validate everything without storing anything
+    #[serde(default, alias = "dry_run")]
+    pub preview: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportRowStatus {
+    /// would be imported, only in previews
+    Valid,
+    Imported,
+    /// imported before, left alone
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Serialize)]
+pub struct ImportRowResult {
+    /// line of the row in the uploaded file
+    pub row: usize,
+    pub status: ImportRowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<Uuid>,
+    /// earlier transactions that look like the same one, imported anyway
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub possible_duplicates: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+    pub preview: bool,
+    pub total_rows: usize,
+    /// rows stored, or that would be stored in a preview
+    pub imported: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+/// a row as it came out of the parser, ready for `insert_transaction` or rejected
+pub struct ParsedRow {
+    pub row: usize,
+    /// stable identity of the entry across imports of the same statement
+    pub import_id: Option<String>,
+    /// the type the category has to be, going by which way the money went
+    pub category_type: Option<CategoryType>,
+    pub transaction: Result<TransactionRequest, Vec<String>>,
+}
@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+#[derive(Serialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "login_outcome", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    /// the password was right, the login continues at `/users/login/2fa`
+    TwoFactorRequired,
+    InvalidCredentials,
+    InvalidTwoFactor,
+    UnverifiedEmail,
+    /// refused without checking anything, does not count as a failure
+    Throttled,
+    /// still being checked, counts as a failure until it is settled
+    Pending,
+}
+
+/// who is attempting to log in, as far as the request tells
+pub struct LoginContext {
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+}
+
+#[derive(FromRow)]
+pub struct LoginFailureRow {
+    pub failures: i64,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct LoginAttemptInfo {
+    pub outcome: LoginOutcome,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,3 +1,15 @@
+pub mod accounts;
+pub mod budgets;
 pub mod categories;
+pub mod currencies;
+pub mod exports;
+pub mod imports;
+pub mod login_attempts;
+pub mod portability;
+pub mod recurring;
+pub mod reports;
+pub mod sessions;
 pub mod transactions;
+pub mod transfers;
+pub mod two_factor;
 pub mod users;
@@ -0,0 +1,27 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `manifest.json` at the root of every personal data archive
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    /// tables included in the archive, each as `<table>.json` and `<table>.csv`
+    pub tables: Vec<String>,
+}
+
+/// the parts of `profile.json` an import carries over, name, email and password
+/// stay those of the account the archive is imported into
+#[derive(Deserialize)]
+pub struct ArchivedProfile {
+    pub base_currency: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportArchiveResponse {
+    pub message: String,
+    /// rows restored per table
+    pub imported: BTreeMap<String, usize>,
+}
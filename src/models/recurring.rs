@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::models::categories::GetUserCategories;
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "recurrence_frequency", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RecurrenceFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "occurrence_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OccurrenceStatus {
+    Posted,
+    Skipped,
+}
+
+fn one() -> i32 {
+    1
+}
+
+/// RRULE-like schedule, occurrences are numbered from zero
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct RecurrenceRule {
+    pub frequency: RecurrenceFrequency,
+    /// repeat every n days, weeks, months or years
+    #[serde(default = "one")]
+    #[sqlx(rename = "repeat_every")]
+    pub interval: i32,
+    /// monthly series only, defaults to the day of starts_at
+    pub day_of_month: Option<i16>,
+    /// first occurrence, later ones keep its time of day
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: Option<DateTime<Utc>>,
+    /// total number of occurrences, skipped ones included
+    #[sqlx(rename = "occurrence_limit")]
+    pub count: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRecurringTransaction {
+    /// category name or slug
+    pub category: String,
+    pub amount: Decimal,
+    /// ISO 4217 code, the user's base currency when left out
+    pub currency: Option<String>,
+    pub description: Option<String>,
+    pub account_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub rule: RecurrenceRule,
+}
+
+/// the schedule itself is fixed once created, only what gets posted can change
+#[derive(Deserialize)]
+pub struct PatchRecurringTransaction {
+    pub amount: Option<Decimal>,
+    pub currency: Option<String>,
+    pub description: Option<String>,
+    pub account_id: Option<Uuid>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub count: Option<i32>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct RecurringTransactionInfo {
+    #[sqlx(rename = "recurring_transaction_id")]
+    pub id: Uuid,
+    pub amount: Decimal,
+    pub currency: String,
+    pub description: Option<String>,
+    pub account_id: Option<Uuid>,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub rule: RecurrenceRule,
+    pub paused: bool,
+    /// index of the next occurrence that has not been posted or skipped yet
+    pub next_occurrence: i32,
+    /// absent once the series is over
+    pub next_due_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(flatten)]
+    pub category: GetUserCategories,
+}
+
+#[derive(Deserialize)]
+pub struct UpcomingParams {
+    /// defaults to 30 days from now
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct UpcomingOccurrence {
+    pub recurring_transaction_id: Uuid,
+    pub occurrence: i32,
+    pub scheduled_for: DateTime<Utc>,
+    pub amount: Decimal,
+    pub currency: String,
+    pub description: Option<String>,
+    pub category: String,
+    pub skipped: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SkipOccurrence {
+    /// index as returned by the upcoming endpoints
+    pub occurrence: i32,
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::models::categories::GetUserCategories;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    Week,
+    #[default]
+    Month,
+}
+
+impl Granularity {
+    /// unit understood by postgres `date_trunc` and `interval`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    pub fn approximate_days(&self) -> i64 {
+        match self {
+            Granularity::Day => 1,
+            Granularity::Week => 7,
+            Granularity::Month => 28,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SummaryParams {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+#[derive(Serialize, Default)]
+pub struct SummaryTotals {
+    pub total_income: Decimal,
+    /// expenses outside of savings categories
+    pub total_expense: Decimal,
+    /// money moved into savings categories
+    pub total_savings: Decimal,
+    /// income - expense - savings
+    pub net_cash_flow: Decimal,
+    /// savings as a percentage of income, absent when there was no income
+    pub savings_rate: Option<Decimal>,
+}
+
+impl SummaryTotals {
+    pub fn new(total_income: Decimal, total_expense: Decimal, total_savings: Decimal) -> Self {
+        let savings_rate = (!total_income.is_zero())
+            .then(|| (total_savings / total_income * Decimal::ONE_HUNDRED).round_dp(2));
+
+        Self {
+            total_income,
+            total_expense,
+            total_savings,
+            net_cash_flow: total_income - total_expense - total_savings,
+            savings_rate,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct SummaryPeriodRow {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub total_income: Decimal,
+    pub total_expense: Decimal,
+    pub total_savings: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct SummaryPeriod {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    #[serde(flatten)]
+    pub totals: SummaryTotals,
+}
+
+#[derive(FromRow)]
+pub struct CurrencySummaryRow {
+    pub currency: String,
+    pub transaction_count: i64,
+    pub total_income: Decimal,
+    pub total_expense: Decimal,
+    pub total_savings: Decimal,
+    pub converted_income: Decimal,
+    pub converted_expense: Decimal,
+    pub converted_savings: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct CurrencySummary {
+    pub currency: String,
+    pub transaction_count: i64,
+    /// totals in the currency the transactions were recorded in
+    pub original: SummaryTotals,
+    /// the same totals in the base currency, each transaction at the rate of its own date
+    pub converted: SummaryTotals,
+    /// converted over original volume, the single rate that would give the same result
+    pub average_rate: Option<Decimal>,
+}
+
+/// every amount is in base_currency unless stated otherwise
+#[derive(Serialize)]
+pub struct SummaryReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub granularity: Granularity,
+    pub base_currency: String,
+    pub totals: SummaryTotals,
+    pub currencies: Vec<CurrencySummary>,
+    pub periods: Vec<SummaryPeriod>,
+}
+
+#[derive(Deserialize)]
+pub struct CategoryReportParams {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(FromRow)]
+pub struct CategoryBreakdownRow {
+    #[sqlx(flatten)]
+    pub category: GetUserCategories,
+    pub total: Decimal,
+    pub transaction_count: i64,
+    pub previous_total: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct CategoryBreakdown {
+    pub category: GetUserCategories,
+    pub total: Decimal,
+    pub transaction_count: i64,
+    /// percentage of the total of the same category type
+    pub share: Option<Decimal>,
+    pub previous_total: Decimal,
+    pub change: Decimal,
+    /// change relative to the previous period, absent when nothing was recorded then
+    pub change_percent: Option<Decimal>,
+}
+
+#[derive(Serialize)]
+pub struct CategoryTypeBreakdown {
+    pub total: Decimal,
+    pub previous_total: Decimal,
+    pub categories: Vec<CategoryBreakdown>,
+}
+
+/// every amount is in base_currency
+#[derive(Serialize)]
+pub struct CategoryReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub base_currency: String,
+    pub previous_from: DateTime<Utc>,
+    pub previous_to: DateTime<Utc>,
+    pub income: CategoryTypeBreakdown,
+    pub expense: CategoryTypeBreakdown,
+}
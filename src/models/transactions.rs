@@ -1,17 +1,30 @@
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 
-use crate::models::categories::GetUserCategories;
+use crate::models::{categories::CategoryType, transfers::TransferDirection};
 
-#[derive(Deserialize)]
+/// tells a field sent as null, Some(None), apart from one left out, None. needs
+/// `#[serde(default)]` on the field as well
+pub fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct TransactionRequest {
     pub category: String,
     pub description: Option<String>,
     pub transaction_date: DateTime<Utc>,
     pub amount: Decimal,
+    /// ISO 4217 code, the user's base currency when left out
+    pub currency: Option<String>,
+    pub account_id: Option<Uuid>,
 }
 
 #[derive(FromRow)]
@@ -19,13 +32,136 @@ pub struct GetCategoryId {
     pub id: Uuid,
 }
 
-#[derive(FromRow, Serialize)]
+#[derive(FromRow, Serialize, Clone)]
 pub struct TransactionInfo {
+    #[sqlx(rename = "transaction_id")]
     pub id: Uuid,
     pub description: Option<String>,
     pub amount: Decimal,
+    pub currency: String,
     pub transaction_date: DateTime<Utc>,
-    #[sqlx(flatten)]
-    #[serde(flatten)]
-    pub category_data: GetUserCategories,
+    pub account_id: Option<Uuid>,
+    pub base_currency: String,
+    /// rate effective on the transaction date, absent when none was uploaded
+    pub exchange_rate: Option<Decimal>,
+    /// amount converted into base_currency with exchange_rate
+    pub base_amount: Option<Decimal>,
+    /// category fields sit next to the transaction's own, as they always have,
+    /// and are absent on the legs of a transfer
+    pub category_id: Option<Uuid>,
+    pub name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    #[sqlx(rename = "type")]
+    pub category_type: Option<CategoryType>,
+    pub is_savings: Option<bool>,
+    /// set on the two legs of a transfer instead of a category
+    pub transfer_id: Option<Uuid>,
+    pub transfer_direction: Option<TransferDirection>,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionPatch {
+    pub amount: Option<Decimal>,
+    pub currency: Option<String>,
+    /// null clears it
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+    pub transaction_date: Option<DateTime<Utc>>,
+    /// category name or slug
+    pub category: Option<String>,
+    pub category_id: Option<Uuid>,
+    pub account_id: Option<Uuid>,
+}
+
+/// filters shared by every endpoint that reads transactions back,
+/// list values are comma separated
+#[derive(Deserialize, Default)]
+pub struct TransactionFilters {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub category_ids: Option<String>,
+    pub categories: Option<String>,
+    pub account_ids: Option<String>,
+    pub currencies: Option<String>,
+    pub category_type: Option<CategoryType>,
+    pub is_savings: Option<bool>,
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    pub q: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionSort {
+    #[default]
+    Date,
+    Amount,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionPageParams {
+    #[serde(default)]
+    pub sort: TransactionSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransactionCursor {
+    pub sort: TransactionSort,
+    pub order: SortOrder,
+    pub value: String,
+    pub id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<TransactionInfo>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DuplicateParams {
+    /// how many days apart two transactions may be to still count as the same one
+    pub window_days: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateWarning {
+    /// position of the transaction in the request
+    pub index: usize,
+    pub transaction_id: Uuid,
+    /// earlier transactions that look like the same one
+    pub possible_duplicates: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct AddTransactionsResponse {
+    pub message: String,
+    pub transaction_ids: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<DuplicateWarning>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicatePairInfo {
+    pub transaction: TransactionInfo,
+    pub duplicate: TransactionInfo,
+}
+
+/// for merges transaction_id is kept and duplicate_id is folded into it
+#[derive(Deserialize)]
+pub struct DuplicatePairRequest {
+    pub transaction_id: Uuid,
+    pub duplicate_id: Uuid,
 }
@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::models::transactions::double_option;
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Debug)]
+#[sqlx(type_name = "transfer_direction", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTransfer {
+    pub from_account_id: Uuid,
+    pub to_account_id: Uuid,
+    pub amount: Decimal,
+    /// ISO 4217 code, the user's base currency when left out
+    pub currency: Option<String>,
+    pub transaction_date: DateTime<Utc>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PatchTransfer {
+    pub from_account_id: Option<Uuid>,
+    pub to_account_id: Option<Uuid>,
+    pub amount: Option<Decimal>,
+    pub currency: Option<String>,
+    pub transaction_date: Option<DateTime<Utc>>,
+    /// null clears it
+    #[serde(default, deserialize_with = "double_option")]
+    pub description: Option<Option<String>>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct TransferInfo {
+    pub id: Uuid,
+    /// absent once the account has been deleted
+    pub from_account_id: Option<Uuid>,
+    pub to_account_id: Option<Uuid>,
+    pub amount: Decimal,
+    pub currency: String,
+    pub transaction_date: DateTime<Utc>,
+    pub description: Option<String>,
+    pub outgoing_transaction_id: Uuid,
+    pub incoming_transaction_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
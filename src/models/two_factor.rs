@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(FromRow)]
+pub struct TwoFactorRow {
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    pub totp_last_step: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub log_message: String,
+    /// base32, for typing into an authenticator app by hand
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub log_message: String,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorDisableRequest {
+    pub password: String,
+    /// a code from the authenticator app or a recovery code
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    /// a code from the authenticator app or a recovery code
+    pub code: String,
+}
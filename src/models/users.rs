@@ -3,15 +3,21 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::models::login_attempts::LoginAttemptInfo;
+
 #[derive(Deserialize)]
 pub struct RegisterUserDetails {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// ISO 4217 code, USD when left out
+    pub base_currency: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct LoginUserDetails {
+    /// the username or the email address
+    #[serde(alias = "email")]
     pub username: String,
     pub password: String,
 }
@@ -29,6 +35,10 @@ pub struct LoginResponseUserDetails {
     pub username: String,
     pub log_message: String,
     pub token: Option<String>,
+    pub refresh_token: Option<String>,
+    /// set instead of the tokens when a two-factor code is needed to finish logging in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub challenge_token: Option<String>,
 }
 
 #[derive(FromRow)]
@@ -41,7 +51,13 @@ pub struct UserDetailRow {
 pub struct UserPasswordRow {
     pub id: Uuid,
     pub name: String,
+    #[sqlx(default)]
+    pub email: String,
     pub password_hash: String,
+    #[sqlx(default)]
+    pub email_verified_at: Option<chrono::DateTime<Utc>>,
+    #[sqlx(default)]
+    pub totp_enabled_at: Option<chrono::DateTime<Utc>>,
 }
 
 #[derive(FromRow, Serialize)]
@@ -52,6 +68,12 @@ pub struct UserProfileDetails {
     pub updated_at: chrono::DateTime<Utc>,
     pub email: String,
     pub is_active: bool,
+    pub email_verified_at: Option<chrono::DateTime<Utc>>,
+    pub two_factor_enabled: bool,
+    pub base_currency: String,
+    /// newest first, failed attempts included
+    #[sqlx(skip)]
+    pub recent_logins: Vec<LoginAttemptInfo>,
 }
 
 #[derive(Deserialize)]
@@ -69,3 +91,26 @@ pub struct Password {
 pub struct HashPassword {
     pub password_hash: String,
 }
+
+#[derive(sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "user_token_purpose", rename_all = "snake_case")]
+pub enum UserTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub token: String,
+    pub new_password: String,
+}
@@ -0,0 +1,25 @@
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+};
+
+use crate::{
+    handlers::accounts::{
+        create_account, delete_account, display_account, list_accounts, update_account,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+pub fn account_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/accounts", post(create_account).get(list_accounts))
+        .route(
+            "/accounts/{id}",
+            get(display_account)
+                .patch(update_account)
+                .delete(delete_account),
+        )
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
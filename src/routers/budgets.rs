@@ -0,0 +1,26 @@
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+};
+
+use crate::{
+    handlers::budgets::{
+        budget_status, create_budget, delete_budget, display_budget, list_budgets, update_budget,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+pub fn budget_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/budgets", post(create_budget).get(list_budgets))
+        .route("/budgets/status", get(budget_status))
+        .route(
+            "/budgets/{id}",
+            get(display_budget)
+                .patch(update_budget)
+                .delete(delete_budget),
+        )
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
@@ -1,12 +1,14 @@
 use axum::{
+    Router,
     middleware::from_fn_with_state,
     routing::{get, post},
-    Router,
 };
 
 use crate::{
-    handlers::categories::{create_category, delete_category, display_category, list_categories},
-    middlewares::{auth::validate_jwt, GlobalAppState},
+    handlers::categories::{
+        create_category, delete_category, display_category, list_categories, update_category,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
 };
 
 pub fn category_routes(state: GlobalAppState) -> Router<GlobalAppState> {
@@ -14,7 +16,10 @@ pub fn category_routes(state: GlobalAppState) -> Router<GlobalAppState> {
         .route("/categories", post(create_category).get(list_categories))
         .route(
             "/categories/{id}",
-            get(display_category).delete(delete_category),
+            get(display_category)
+                .patch(update_category)
+                .delete(delete_category),
         )
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
         .route_layer(from_fn_with_state(state, validate_jwt))
 }
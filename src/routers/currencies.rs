@@ -0,0 +1,21 @@
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{delete, post},
+};
+
+use crate::{
+    handlers::currencies::{delete_exchange_rate, list_exchange_rates, upload_exchange_rates},
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+pub fn currency_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route(
+            "/exchange-rates",
+            post(upload_exchange_rates).get(list_exchange_rates),
+        )
+        .route("/exchange-rates/{id}", delete(delete_exchange_rate))
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
@@ -0,0 +1,12 @@
+use axum::{Router, middleware::from_fn_with_state, routing::get};
+
+use crate::{
+    handlers::exports::export_transactions,
+    middlewares::{GlobalAppState, auth::validate_jwt},
+};
+
+pub fn export_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/exports/transactions", get(export_transactions))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
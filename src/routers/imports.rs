@@ -0,0 +1,19 @@
+use axum::{Router, extract::DefaultBodyLimit, middleware::from_fn_with_state, routing::post};
+
+use crate::{
+    handlers::imports::{import_csv, import_ofx, import_qif},
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+/// statements are a lot bigger than the default 2MB json bodies
+const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024;
+
+pub fn import_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/imports/csv", post(import_csv))
+        .route("/imports/ofx", post(import_ofx))
+        .route("/imports/qif", post(import_qif))
+        .layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE))
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
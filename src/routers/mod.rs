@@ -1,7 +1,16 @@
-use crate::middlewares::GlobalAppState;
-use axum::Router;
+use crate::errors::GlobalAppError;
+use crate::middlewares::{GlobalAppState, request_id::assign_request_id};
+use axum::{Router, middleware::from_fn};
+mod accounts;
+mod budgets;
 mod categories;
+mod currencies;
+mod exports;
+mod imports;
+mod recurring;
+mod reports;
 mod transactions;
+mod transfers;
 mod users;
 
 pub fn app_router(state: GlobalAppState) -> Router {
@@ -9,5 +18,15 @@ pub fn app_router(state: GlobalAppState) -> Router {
         .merge(users::user_routes(state.clone()))
         .merge(categories::category_routes(state.clone()))
         .merge(transactions::transaction_routes(state.clone()))
+        .merge(reports::report_routes(state.clone()))
+        .merge(budgets::budget_routes(state.clone()))
+        .merge(accounts::account_routes(state.clone()))
+        .merge(transfers::transfer_routes(state.clone()))
+        .merge(currencies::currency_routes(state.clone()))
+        .merge(recurring::recurring_routes(state.clone()))
+        .merge(imports::import_routes(state.clone()))
+        .merge(exports::export_routes(state.clone()))
+        .fallback(|| async { GlobalAppError::NotFound("route not found!".to_string()) })
+        .layer(from_fn(assign_request_id))
         .with_state(state)
 }
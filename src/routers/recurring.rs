@@ -0,0 +1,48 @@
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+};
+
+use crate::{
+    handlers::recurring::{
+        create_recurring_transaction, delete_recurring_transaction, display_recurring_transaction,
+        list_recurring_transactions, list_series_upcoming_occurrences, list_upcoming_occurrences,
+        pause_recurring_transaction, resume_recurring_transaction, skip_occurrence,
+        update_recurring_transaction,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+pub fn recurring_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route(
+            "/recurring-transactions",
+            post(create_recurring_transaction).get(list_recurring_transactions),
+        )
+        .route(
+            "/recurring-transactions/upcoming",
+            get(list_upcoming_occurrences),
+        )
+        .route(
+            "/recurring-transactions/{id}",
+            get(display_recurring_transaction)
+                .patch(update_recurring_transaction)
+                .delete(delete_recurring_transaction),
+        )
+        .route(
+            "/recurring-transactions/{id}/upcoming",
+            get(list_series_upcoming_occurrences),
+        )
+        .route(
+            "/recurring-transactions/{id}/pause",
+            post(pause_recurring_transaction),
+        )
+        .route(
+            "/recurring-transactions/{id}/resume",
+            post(resume_recurring_transaction),
+        )
+        .route("/recurring-transactions/{id}/skip", post(skip_occurrence))
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
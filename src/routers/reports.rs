@@ -0,0 +1,13 @@
+use axum::{Router, middleware::from_fn_with_state, routing::get};
+
+use crate::{
+    handlers::reports::{category_report, summary_report},
+    middlewares::{GlobalAppState, auth::validate_jwt},
+};
+
+pub fn report_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/reports/summary", get(summary_report))
+        .route("/reports/categories", get(category_report))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
@@ -1,8 +1,15 @@
-use axum::{middleware::from_fn_with_state, routing::post, Router};
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+};
 
 use crate::{
-    handlers::transactions::{add_transactions, list_transactions},
-    middlewares::{auth::validate_jwt, GlobalAppState},
+    handlers::transactions::{
+        add_transactions, delete_transaction, dismiss_duplicates, display_transaction,
+        list_duplicates, list_transactions, merge_duplicates, update_transaction,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
 };
 
 pub fn transaction_routes(state: GlobalAppState) -> Router<GlobalAppState> {
@@ -11,5 +18,15 @@ pub fn transaction_routes(state: GlobalAppState) -> Router<GlobalAppState> {
             "/transactions",
             post(add_transactions).get(list_transactions),
         )
+        .route("/transactions/duplicates", get(list_duplicates))
+        .route("/transactions/duplicates/merge", post(merge_duplicates))
+        .route("/transactions/duplicates/dismiss", post(dismiss_duplicates))
+        .route(
+            "/transactions/{id}",
+            get(display_transaction)
+                .patch(update_transaction)
+                .delete(delete_transaction),
+        )
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
         .route_layer(from_fn_with_state(state, validate_jwt))
 }
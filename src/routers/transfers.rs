@@ -0,0 +1,25 @@
+use axum::{
+    Router,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+};
+
+use crate::{
+    handlers::transfers::{
+        create_transfer, delete_transfer, display_transfer, list_transfers, update_transfer,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
+};
+
+pub fn transfer_routes(state: GlobalAppState) -> Router<GlobalAppState> {
+    Router::new()
+        .route("/transfers", post(create_transfer).get(list_transfers))
+        .route(
+            "/transfers/{id}",
+            get(display_transfer)
+                .patch(update_transfer)
+                .delete(delete_transfer),
+        )
+        .route_layer(from_fn_with_state(state.clone(), idempotency))
+        .route_layer(from_fn_with_state(state, validate_jwt))
+}
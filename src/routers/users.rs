@@ -1,20 +1,53 @@
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{get, post, put},
 };
 
 use crate::{
-    handlers::users::{delete_user, login, my_profile, register, update_password},
-    middlewares::{GlobalAppState, auth::validate_jwt},
+    handlers::users::{
+        confirm_two_factor, delete_user, disable_two_factor, export_user_data, forgot_password,
+        import_user_data, login, login_two_factor, logout, logout_all, my_profile, refresh_token,
+        register, resend_verification_email, reset_password, setup_two_factor,
+        update_base_currency, update_password, verify_email,
+    },
+    middlewares::{GlobalAppState, auth::validate_jwt, idempotency::idempotency},
 };
 
+/// archives are zipped, the same limit as statement imports is plenty
+const MAX_ARCHIVE_SIZE: usize = 10 * 1024 * 1024;
+
 pub fn user_routes(state: GlobalAppState) -> Router<GlobalAppState> {
     Router::new()
         .route(
             "/users/me",
             get(my_profile).patch(update_password).delete(delete_user),
         )
+        .route("/users/me/base-currency", put(update_base_currency))
+        .route("/users/me/export", post(export_user_data))
+        .route(
+            "/users/me/import",
+            post(import_user_data).layer(DefaultBodyLimit::max(MAX_ARCHIVE_SIZE)),
+        )
+        .route("/users/me/2fa/setup", post(setup_two_factor))
+        .route("/users/me/2fa/confirm", post(confirm_two_factor))
+        .route("/users/me/2fa/disable", post(disable_two_factor))
+        .route("/users/logout", post(logout))
+        .route("/users/logout-all", post(logout_all))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            idempotency,
+        ))
         .route_layer(axum::middleware::from_fn_with_state(state, validate_jwt))
         .route("/users/register", post(register))
         .route("/users/login", post(login))
+        .route("/users/login/2fa", post(login_two_factor))
+        .route("/users/token/refresh", post(refresh_token))
+        .route("/users/verify-email", post(verify_email))
+        .route(
+            "/users/verify-email/resend",
+            post(resend_verification_email),
+        )
+        .route("/users/password/forgot", post(forgot_password))
+        .route("/users/password/reset", post(reset_password))
 }